@@ -0,0 +1,236 @@
+extern crate bvh;
+extern crate smallpt;
+
+use bvh::aabb::{Bounded, AABB};
+use bvh::bvh::{BVHNode, BVH};
+use bvh::ray::Ray as NewRay;
+use bvh::Vector3;
+use smallpt::*;
+use std::fs;
+use std::time::Instant;
+
+// Compares the in-crate BVH against the bvh crate on a large triangle mesh: either the OBJ file given on
+// the command line, or a tessellated and displaced sphere.
+//
+//   cargo run --release --example bvh_benchmark [mesh.obj]
+fn main() {
+	let mut triangles = match std::env::args().nth(1) {
+		Some(path) => load_obj(&path),
+		None => sphere_mesh(1024, 512),
+	};
+
+	let bounds = triangles.iter().fold(AABB::empty(), |bounds, triangle| {
+		bounds.join(&triangle.aabb())
+	});
+	let center = bounds.center();
+	let radius = 0.5 * bounds.size().length();
+	let rays = camera_rays(center, radius, 512, 512);
+
+	println!("{} triangles, {} rays", triangles.len(), rays.len());
+
+	// bvh crate: gather every candidate, then test them all
+	let start = Instant::now();
+	let crate_bvh = BVH::build(&mut triangles);
+	let build_time = start.elapsed();
+
+	let start = Instant::now();
+	let mut crate_hits = 0;
+	let mut hit = Hit::init();
+	for ray in rays.iter() {
+		let bvh_ray = NewRay::new(ray.origin, ray.direction);
+		let mut indices = vec![];
+		BVHNode::traverse_recursive(&crate_bvh.nodes, 0, &bvh_ray, &mut indices);
+
+		let mut closest = f32::INFINITY;
+		for index in indices {
+			if triangles[index].intersect(ray, &mut hit) && hit.t > 1e-6 && hit.t < closest {
+				closest = hit.t;
+			}
+		}
+		if closest.is_finite() {
+			crate_hits += 1;
+		}
+	}
+	report(
+		"bvh crate",
+		build_time,
+		start.elapsed(),
+		rays.len(),
+		crate_hits,
+	);
+
+	// In-crate binary SAH hierarchy, which wide BVHs are collapsed from. Their build times only cover
+	// collapsing it.
+	let start = Instant::now();
+	let triangle_bounds: Vec<_> = triangles.iter().map(|triangle| triangle.aabb()).collect();
+	let flat_bvh = FlatBVH::build(&triangle_bounds);
+	println!(
+		"{:>12}: build {:>8.1}ms",
+		"FlatBVH",
+		start.elapsed().as_secs_f64() * 1000.0
+	);
+
+	// Wide BVHs with each way of testing boxes: ordered traversal, pruned against the closest hit
+	for &box_test in [BoxTest::Scalar, BoxTest::Sse2, BoxTest::Avx2].iter() {
+		if !box_test.is_supported() {
+			continue;
+		}
+
+		let start = Instant::now();
+		let bvh4 = BVH4::collapse(&flat_bvh).with_box_test(box_test);
+		let build_time = start.elapsed();
+		let (trace_time, hits) = trace(&triangles, &rays, |ray, intersect| {
+			bvh4.intersect(ray, f32::INFINITY, intersect)
+		});
+		let name = format!("BVH4 {:?}", bvh4.box_test());
+		report(&name, build_time, trace_time, rays.len(), hits);
+
+		let start = Instant::now();
+		let bvh8 = BVH8::collapse(&flat_bvh).with_box_test(box_test);
+		let build_time = start.elapsed();
+		let (trace_time, hits) = trace(&triangles, &rays, |ray, intersect| {
+			bvh8.intersect(ray, f32::INFINITY, intersect)
+		});
+		let name = format!("BVH8 {:?}", bvh8.box_test());
+		report(&name, build_time, trace_time, rays.len(), hits);
+	}
+}
+
+// Closest hit of every ray through one of the in-crate hierarchies, which is handed a ray and the
+// primitive test, returning the time taken and the number of hits
+fn trace<T>(triangles: &[Triangle], rays: &[Ray], traverse: T) -> (std::time::Duration, usize)
+where
+	T: Fn(&Ray, &mut dyn FnMut(usize, f32) -> Option<f32>),
+{
+	let start = Instant::now();
+	let mut hits = 0;
+	let mut hit = Hit::init();
+
+	for ray in rays.iter() {
+		let mut closest = f32::INFINITY;
+		traverse(ray, &mut |index, t_max| {
+			if triangles[index].intersect(ray, &mut hit) && hit.t > 1e-6 && hit.t < t_max {
+				closest = hit.t;
+				Some(hit.t)
+			} else {
+				None
+			}
+		});
+		if closest.is_finite() {
+			hits += 1;
+		}
+	}
+
+	(start.elapsed(), hits)
+}
+
+fn report(
+	name: &str,
+	build_time: std::time::Duration,
+	trace_time: std::time::Duration,
+	num_rays: usize,
+	num_hits: usize,
+) {
+	println!(
+		"{:>12}: build {:>8.1}ms, trace {:>8.1}ms ({:.2} Mrays/s), {} hits",
+		name,
+		build_time.as_secs_f64() * 1000.0,
+		trace_time.as_secs_f64() * 1000.0,
+		num_rays as f64 / trace_time.as_secs_f64() / 1e6,
+		num_hits
+	);
+}
+
+// Primary rays from a pinhole looking at the mesh
+fn camera_rays(center: Vector3, radius: f32, width: usize, height: usize) -> Vec<Ray> {
+	let origin = center + Vector3::new(0.3, 0.4, 1.0).normalize() * radius * 2.5;
+	let forward = (center - origin).normalize();
+	let right = forward.cross(Vector3::new(0.0, 1.0, 0.0)).normalize();
+	let up = right.cross(forward);
+
+	let mut rays = Vec::with_capacity(width * height);
+	for y in 0..height {
+		for x in 0..width {
+			let u = (x as f32 + 0.5) / width as f32 * 2.0 - 1.0;
+			let v = 1.0 - (y as f32 + 0.5) / height as f32 * 2.0;
+			let direction = (forward * 2.5 + right * u + up * v).normalize();
+			rays.push(Ray::new(origin, direction));
+		}
+	}
+
+	rays
+}
+
+// UV sphere with a bumpy surface, so the triangles vary in size and orientation
+fn sphere_mesh(slices: usize, stacks: usize) -> Vec<Triangle> {
+	let vertex = |i: usize, j: usize| {
+		let phi = i as f32 / slices as f32 * 2.0 * std::f32::consts::PI;
+		let theta = j as f32 / stacks as f32 * std::f32::consts::PI;
+		let r = 1.0 + 0.05 * (phi * 17.0).sin() * (theta * 23.0).sin();
+		Vector3::new(
+			r * theta.sin() * phi.cos(),
+			r * theta.cos(),
+			r * theta.sin() * phi.sin(),
+		)
+	};
+
+	let mut triangles = Vec::with_capacity(slices * stacks * 2);
+	for j in 0..stacks {
+		for i in 0..slices {
+			let (p00, p10) = (vertex(i, j), vertex(i + 1, j));
+			let (p01, p11) = (vertex(i, j + 1), vertex(i + 1, j + 1));
+
+			if j > 0 {
+				triangles.push(Triangle::new(p00, p10, p11, Material::white()));
+			}
+			if j + 1 < stacks {
+				triangles.push(Triangle::new(p00, p11, p01, Material::white()));
+			}
+		}
+	}
+
+	triangles
+}
+
+// Positions and faces of a Wavefront OBJ file, with polygons split into fans
+fn load_obj(path: &str) -> Vec<Triangle> {
+	let source = fs::read_to_string(path).expect("Could not read the OBJ file");
+	let mut positions = vec![];
+	let mut triangles = vec![];
+
+	for line in source.lines() {
+		let mut tokens = line.split_whitespace();
+		match tokens.next() {
+			Some("v") => {
+				let p: Vec<f32> = tokens.take(3).map(|t| t.parse().unwrap_or(0.0)).collect();
+				if p.len() == 3 {
+					positions.push(Vector3::new(p[0], p[1], p[2]));
+				}
+			}
+			Some("f") => {
+				let face: Vec<usize> = tokens
+					.filter_map(|t| t.split('/').next()?.parse::<i64>().ok())
+					.map(|index| {
+						if index < 0 {
+							(positions.len() as i64 + index) as usize
+						} else {
+							index as usize - 1
+						}
+					})
+					.collect();
+
+				for k in 2..face.len() {
+					triangles.push(Triangle::new(
+						positions[face[0]],
+						positions[face[k - 1]],
+						positions[face[k]],
+						Material::white(),
+					));
+				}
+			}
+			_ => {}
+		}
+	}
+
+	triangles
+}
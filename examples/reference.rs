@@ -23,7 +23,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(1.0, 1.0, 1.0),
-			BSDF::Mirror,
+			Mirror,
 		),
 	)));
 
@@ -34,7 +34,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(1.0, 1.0, 1.0),
-			BSDF::Glass,
+			Glass::new(1.5),
 		),
 	)));
 
@@ -46,7 +46,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(0.75, 0.75, 0.75),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -57,7 +57,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(0.75, 0.25, 0.25),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -68,7 +68,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(0.25, 0.25, 0.75),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -79,7 +79,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(0.75, 0.75, 0.75),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -90,7 +90,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(0.0, 0.0, 0.0),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -101,7 +101,7 @@ fn main() {
 		Material::new(
 			Vector3::new(0.0, 0.0, 0.0),
 			Vector3::new(0.75, 0.75, 0.75),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -116,7 +116,7 @@ fn main() {
 		Material::new(
 			Vector3::new(12.0, 12.0, 12.0),
 			Vector3::new(0.0, 0.0, 0.0),
-			BSDF::Diffuse,
+			Diffuse,
 		),
 	)));
 
@@ -0,0 +1,310 @@
+use bvh::aabb::AABB;
+use bvh::Vector3;
+
+// Number of buckets candidate splits are binned into along each axis
+const NUM_BUCKETS: usize = 12;
+
+// Leaves are made as soon as they hold this few primitives, or when the surface area heuristic finds
+// splitting costs more than testing every primitive
+const MAX_LEAF_SIZE: usize = 4;
+
+// Cost of visiting a node, relative to testing a primitive
+const TRAVERSAL_COST: f32 = 0.5;
+
+// Past this depth, nodes are split at their median so the tree stays within the traversal stack of the
+// wide hierarchies collapsed from it
+const MAX_SAH_DEPTH: usize = 32;
+
+// Node of the binary hierarchy FlatBVH builds. Interior nodes are directly followed by their first child.
+#[derive(Copy, Clone, Debug)]
+pub struct FlatNode {
+	pub min: Vector3,
+	pub max: Vector3,
+	// Index of the second child for interior nodes, or of the first primitive for leaves
+	pub offset: u32,
+	// Number of primitives, or 0 for interior nodes
+	pub count: u16,
+}
+
+impl FlatNode {
+	pub fn is_leaf(&self) -> bool {
+		self.count > 0
+	}
+}
+
+// Build step of WideBVH: a binary hierarchy built with the surface area heuristic [MacDonald and Booth
+// 1990], with its nodes stored depth-first, which WideBVH::collapse turns into the wide hierarchy rays
+// traverse. It only knows primitives by their index and bounds.
+#[derive(Clone, Debug, Default)]
+pub struct FlatBVH {
+	pub nodes: Vec<FlatNode>,
+	// Primitive indices, grouped by leaf
+	pub indices: Vec<u32>,
+}
+
+#[derive(Copy, Clone)]
+struct Bucket {
+	count: usize,
+	bounds: AABB,
+}
+
+impl FlatBVH {
+	pub fn build(bounds: &[AABB]) -> FlatBVH {
+		let mut bvh = FlatBVH {
+			nodes: Vec::with_capacity(bounds.len() * 2),
+			indices: (0..bounds.len() as u32).collect(),
+		};
+
+		if !bounds.is_empty() {
+			let centroids: Vec<Vector3> = bounds.iter().map(|b| b.center()).collect();
+			bvh.build_recursive(bounds, &centroids, 0, bounds.len(), 0);
+		}
+
+		bvh
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	pub fn bounds(&self) -> AABB {
+		match self.nodes.first() {
+			Some(root) => AABB::with_bounds(root.min, root.max),
+			None => AABB::empty(),
+		}
+	}
+
+	fn build_recursive(
+		&mut self,
+		bounds: &[AABB],
+		centroids: &[Vector3],
+		start: usize,
+		end: usize,
+		depth: usize,
+	) -> usize {
+		let node = self.nodes.len();
+		let count = end - start;
+
+		let mut node_bounds = AABB::empty();
+		let mut centroid_bounds = AABB::empty();
+		for &index in self.indices[start..end].iter() {
+			node_bounds.join_mut(&bounds[index as usize]);
+			centroid_bounds.grow_mut(&centroids[index as usize]);
+		}
+
+		self.nodes.push(FlatNode {
+			min: node_bounds.min,
+			max: node_bounds.max,
+			offset: start as u32,
+			count: count as u16,
+		});
+
+		if count <= 1 {
+			return node;
+		}
+
+		let extent = centroid_bounds.size();
+		let axis = if extent.x >= extent.y && extent.x >= extent.z {
+			0
+		} else if extent.y >= extent.z {
+			1
+		} else {
+			2
+		};
+
+		let middle = if extent[axis] <= 0.0 {
+			// All the centroids coincide, so no split is better than another
+			if count <= MAX_LEAF_SIZE {
+				return node;
+			}
+			start + count / 2
+		} else if depth >= MAX_SAH_DEPTH {
+			let middle = start + count / 2;
+			self.indices[start..end].select_nth_unstable_by(count / 2, |&a, &b| {
+				let a = centroids[a as usize][axis];
+				let b = centroids[b as usize][axis];
+				a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+			});
+			middle
+		} else {
+			let bucket_of = |index: u32| {
+				let offset =
+					(centroids[index as usize][axis] - centroid_bounds.min[axis]) / extent[axis];
+				((offset * NUM_BUCKETS as f32) as usize).min(NUM_BUCKETS - 1)
+			};
+
+			let mut buckets = [Bucket {
+				count: 0,
+				bounds: AABB::empty(),
+			}; NUM_BUCKETS];
+			for &index in self.indices[start..end].iter() {
+				let bucket = &mut buckets[bucket_of(index)];
+				bucket.count += 1;
+				bucket.bounds.join_mut(&bounds[index as usize]);
+			}
+
+			// Cost of splitting after each bucket, sweeping the buckets from both ends
+			let mut costs = [0.0; NUM_BUCKETS - 1];
+			let mut left = Bucket {
+				count: 0,
+				bounds: AABB::empty(),
+			};
+			for (i, cost) in costs.iter_mut().enumerate() {
+				left.count += buckets[i].count;
+				left.bounds.join_mut(&buckets[i].bounds);
+				*cost = left.count as f32 * surface_area(&left.bounds);
+			}
+			let mut right = Bucket {
+				count: 0,
+				bounds: AABB::empty(),
+			};
+			for i in (1..NUM_BUCKETS).rev() {
+				right.count += buckets[i].count;
+				right.bounds.join_mut(&buckets[i].bounds);
+				costs[i - 1] += right.count as f32 * surface_area(&right.bounds);
+			}
+
+			let mut split = 0;
+			for i in 1..costs.len() {
+				if costs[i] < costs[split] {
+					split = i;
+				}
+			}
+			let cost = TRAVERSAL_COST + costs[split] / surface_area(&node_bounds);
+
+			if count <= MAX_LEAF_SIZE && cost >= count as f32 {
+				return node;
+			}
+
+			let mut middle = start;
+			for i in start..end {
+				if bucket_of(self.indices[i]) <= split {
+					self.indices.swap(i, middle);
+					middle += 1;
+				}
+			}
+
+			if middle == start || middle == end {
+				start + count / 2
+			} else {
+				middle
+			}
+		};
+
+		self.build_recursive(bounds, centroids, start, middle, depth + 1);
+		let second = self.build_recursive(bounds, centroids, middle, end, depth + 1);
+
+		self.nodes[node].offset = second as u32;
+		self.nodes[node].count = 0;
+
+		node
+	}
+}
+
+// Surface area of a box, which is zero rather than undefined when it is empty
+fn surface_area(bounds: &AABB) -> f32 {
+	if bounds.is_empty() {
+		0.0
+	} else {
+		bounds.surface_area()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::rngs::StdRng;
+	use rand::{Rng, SeedableRng};
+
+	fn contains(outer: &AABB, inner: &AABB) -> bool {
+		outer.min.cmple(inner.min).all() && inner.max.cmple(outer.max).all()
+	}
+
+	// Check the structure of the hierarchy, returning its depth
+	fn validate(bvh: &FlatBVH, bounds: &[AABB]) -> usize {
+		let mut seen = vec![false; bounds.len()];
+		let depth = validate_node(bvh, bounds, 0, &mut seen);
+		assert!(
+			seen.iter().all(|&seen| seen),
+			"primitive missing from the leaves"
+		);
+		depth
+	}
+
+	fn validate_node(bvh: &FlatBVH, bounds: &[AABB], node: usize, seen: &mut [bool]) -> usize {
+		let flat = &bvh.nodes[node];
+		let node_bounds = AABB::with_bounds(flat.min, flat.max);
+
+		let children = if flat.is_leaf() {
+			let start = flat.offset as usize;
+			for &index in bvh.indices[start..start + flat.count as usize].iter() {
+				assert!(!seen[index as usize], "primitive in several leaves");
+				seen[index as usize] = true;
+				assert!(contains(&node_bounds, &bounds[index as usize]));
+			}
+			return 1;
+		} else {
+			[node + 1, flat.offset as usize]
+		};
+
+		let mut depth = 0;
+		for &child in children.iter() {
+			let child_bounds = AABB::with_bounds(bvh.nodes[child].min, bvh.nodes[child].max);
+			assert!(contains(&node_bounds, &child_bounds));
+			depth = depth.max(validate_node(bvh, bounds, child, seen) + 1);
+		}
+		depth
+	}
+
+	fn random_box(rng: &mut StdRng) -> AABB {
+		let p = Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+		let size = Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 0.5;
+		AABB::with_bounds(p, p + size)
+	}
+
+	#[test]
+	fn random_boxes() {
+		let mut rng = StdRng::seed_from_u64(1);
+		for &count in [0, 1, 2, 5, 100, 1000].iter() {
+			let bounds: Vec<AABB> = (0..count).map(|_| random_box(&mut rng)).collect();
+			let bvh = FlatBVH::build(&bounds);
+
+			assert_eq!(bvh.is_empty(), count == 0);
+			if count > 0 {
+				validate(&bvh, &bounds);
+			}
+		}
+	}
+
+	#[test]
+	fn flat_and_coincident_boxes() {
+		let mut rng = StdRng::seed_from_u64(2);
+
+		// Axis-aligned rectangles, flat along one axis
+		let bounds: Vec<AABB> = (0..300)
+			.map(|i| {
+				let mut b = random_box(&mut rng);
+				b.max[i % 3] = b.min[i % 3];
+				b
+			})
+			.collect();
+		validate(&FlatBVH::build(&bounds), &bounds);
+
+		// Identical boxes, which no split can separate
+		let bounds = vec![AABB::with_bounds(Vector3::splat(1.0), Vector3::splat(2.0)); 100];
+		validate(&FlatBVH::build(&bounds), &bounds);
+	}
+
+	#[test]
+	fn depth_is_bounded() {
+		// Boxes doubling in distance from the origin, which make for a lopsided tree
+		let bounds: Vec<AABB> = (0..120)
+			.map(|i| {
+				let x = 2f32.powi(i);
+				AABB::with_bounds(Vector3::new(x, 0.0, 0.0), Vector3::new(x * 1.01, 1.0, 1.0))
+			})
+			.collect();
+		let depth = validate(&FlatBVH::build(&bounds), &bounds);
+		assert!(depth <= 64, "depth {}", depth);
+	}
+}
@@ -1,6 +1,260 @@
-#[derive(Copy, Clone)]
-pub enum BSDF {
-	Diffuse,
-	Mirror,
-	Glass,
+use bvh::Vector3;
+use hit::Hit;
+use sampling::sample_cosine_hemisphere;
+use std::f32::consts::PI;
+
+// A direction sampled from a BSDF. Directions are expressed in the local shading frame,
+// where the shading normal is the z axis.
+#[derive(Copy, Clone, Debug)]
+pub struct BSDFSample {
+	pub wi: Vector3,
+	// BSDF value times cosine, divided by the pdf
+	pub weight: Vector3,
+	// Probability density with respect to solid angle (meaningless for specular samples)
+	pub pdf: f32,
+	pub specular: bool,
+}
+
+impl BSDFSample {
+	pub fn new(wi: Vector3, weight: Vector3, pdf: f32, specular: bool) -> BSDFSample {
+		BSDFSample {
+			wi,
+			weight,
+			pdf,
+			specular,
+		}
+	}
+}
+
+// Schlick's approximation of the dielectric Fresnel reflectance, using the cosine on the outside of the medium
+pub fn fresnel_schlick(cos_outside: f32, ior: f32) -> f32 {
+	let a = ior - 1.0;
+	let b = ior + 1.0;
+	let base_reflectance = a * a / (b * b);
+	let c = 1.0 - cos_outside;
+
+	base_reflectance + (1.0 - base_reflectance) * c * c * c * c * c
+}
+
+// Scattering model, evaluated in the local shading frame. `wo` points towards the viewer,
+// and both `wo` and `wi` point away from the surface.
+pub trait BSDF: Send + Sync {
+	fn sample(&self, hit: &Hit, wo: Vector3, uc: f32, u1: f32, u2: f32) -> Option<BSDFSample>;
+
+	// BSDF value, not including the cosine term
+	fn eval(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3;
+
+	// Probability density of `sample` returning `wi`, with respect to solid angle
+	fn pdf(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> f32;
+
+	// Perfectly specular BSDFs can't be evaluated for arbitrary directions, and are skipped by light sampling.
+	// It can depend on the hit, as textures scale the roughness.
+	fn is_specular(&self, _hit: &Hit) -> bool {
+		false
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Diffuse;
+
+impl BSDF for Diffuse {
+	fn sample(&self, hit: &Hit, wo: Vector3, _uc: f32, u1: f32, u2: f32) -> Option<BSDFSample> {
+		let wi = sample_cosine_hemisphere(u1, u2);
+
+		let pdf = self.pdf(hit, wo, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		Some(BSDFSample::new(wi, hit.material.albedo, pdf, false))
+	}
+
+	fn eval(&self, hit: &Hit, _wo: Vector3, wi: Vector3) -> Vector3 {
+		if wi.z > 0.0 {
+			hit.material.albedo / PI
+		} else {
+			Vector3::new(0.0, 0.0, 0.0)
+		}
+	}
+
+	fn pdf(&self, _hit: &Hit, _wo: Vector3, wi: Vector3) -> f32 {
+		wi.z.max(0.0) / PI
+	}
+}
+
+// Rough diffuse surface made of V-shaped Lambertian microfacets [Oren and Nayar 1994], with `sigma` the
+// standard deviation of the facet angles, in radians. A sigma of 0 is exactly Lambertian.
+#[derive(Copy, Clone, Debug)]
+pub struct OrenNayar {
+	pub sigma: f32,
+	a: f32,
+	b: f32,
+}
+
+impl OrenNayar {
+	pub fn new(sigma: f32) -> OrenNayar {
+		let sigma2 = sigma * sigma;
+
+		OrenNayar {
+			sigma,
+			a: 1.0 - sigma2 / (2.0 * (sigma2 + 0.33)),
+			b: 0.45 * sigma2 / (sigma2 + 0.09),
+		}
+	}
+}
+
+impl BSDF for OrenNayar {
+	fn sample(&self, hit: &Hit, wo: Vector3, _uc: f32, u1: f32, u2: f32) -> Option<BSDFSample> {
+		let wi = sample_cosine_hemisphere(u1, u2);
+
+		let pdf = self.pdf(hit, wo, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		Some(BSDFSample::new(
+			wi,
+			self.eval(hit, wo, wi) * (wi.z / pdf),
+			pdf,
+			false,
+		))
+	}
+
+	fn eval(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		if wi.z <= 0.0 || wo.z <= 0.0 {
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+
+		let sin_o = (wo.x * wo.x + wo.y * wo.y).sqrt();
+		let sin_i = (wi.x * wi.x + wi.y * wi.y).sqrt();
+
+		// Cosine of the azimuthal angle between both directions
+		let cos_phi = if sin_o > 1e-4 && sin_i > 1e-4 {
+			((wo.x * wi.x + wo.y * wi.y) / (sin_o * sin_i)).max(0.0)
+		} else {
+			0.0
+		};
+
+		// sin(alpha) * tan(beta), with alpha the largest and beta the smallest polar angle
+		let (sin_alpha, tan_beta) = if wi.z < wo.z {
+			(sin_i, sin_o / wo.z)
+		} else {
+			(sin_o, sin_i / wi.z)
+		};
+
+		hit.material.albedo / PI * (self.a + self.b * cos_phi * sin_alpha * tan_beta)
+	}
+
+	fn pdf(&self, _hit: &Hit, _wo: Vector3, wi: Vector3) -> f32 {
+		wi.z.max(0.0) / PI
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Mirror;
+
+impl BSDF for Mirror {
+	fn sample(&self, hit: &Hit, wo: Vector3, _uc: f32, _u1: f32, _u2: f32) -> Option<BSDFSample> {
+		let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+
+		Some(BSDFSample::new(wi, hit.material.albedo, 1.0, true))
+	}
+
+	fn eval(&self, _hit: &Hit, _wo: Vector3, _wi: Vector3) -> Vector3 {
+		Vector3::new(0.0, 0.0, 0.0)
+	}
+
+	fn pdf(&self, _hit: &Hit, _wo: Vector3, _wi: Vector3) -> f32 {
+		0.0
+	}
+
+	fn is_specular(&self, _hit: &Hit) -> bool {
+		true
+	}
+}
+
+// Smooth dielectric, with the index of refraction of the inside medium (the outside is assumed to be air)
+#[derive(Copy, Clone, Debug)]
+pub struct Glass {
+	pub ior: f32,
+}
+
+impl Glass {
+	pub fn new(ior: f32) -> Glass {
+		Glass { ior }
+	}
+
+	pub fn water() -> Glass {
+		Glass::new(1.333)
+	}
+
+	pub fn diamond() -> Glass {
+		Glass::new(2.42)
+	}
+}
+
+impl Default for Glass {
+	fn default() -> Glass {
+		Glass::new(1.5)
+	}
+}
+
+impl BSDF for Glass {
+	fn sample(&self, hit: &Hit, wo: Vector3, uc: f32, _u1: f32, _u2: f32) -> Option<BSDFSample> {
+		let albedo = hit.material.albedo;
+		let reflection = Vector3::new(-wo.x, -wo.y, wo.z);
+
+		// Compute input-output IOR. The shading normal faces the viewer, so the ray enters
+		// the medium when it agrees with the outward geometric normal.
+		let into = hit.n.dot(hit.ng) > 0.0;
+		let nc = 1.0;
+		let nt = self.ior;
+		let nnt = if into { nc / nt } else { nt / nc };
+
+		// Compute fresnel
+		let ddn = -wo.z;
+		let cos2t = 1.0 - nnt * nnt * (1.0 - ddn * ddn);
+
+		if cos2t < 0.0 {
+			// Total internal reflection
+			return Some(BSDFSample::new(reflection, albedo, 1.0, true));
+		}
+
+		let transmitted = Vector3::new(-wo.x * nnt, -wo.y * nnt, -cos2t.sqrt()).normalize();
+
+		let reflectance = fresnel_schlick(if into { -ddn } else { -transmitted.z }, nt / nc);
+		let transmittance = 1.0 - reflectance;
+		let rr_propability = 0.25 + 0.5 * reflectance;
+		let reflectance_propability = reflectance / rr_propability;
+		let transmittance_propability = transmittance / (1.0 - rr_propability);
+
+		// Russian roulette between reflectance and transmittance
+		if uc < rr_propability {
+			Some(BSDFSample::new(
+				reflection,
+				albedo * reflectance_propability,
+				rr_propability,
+				true,
+			))
+		} else {
+			Some(BSDFSample::new(
+				transmitted,
+				albedo * transmittance_propability,
+				1.0 - rr_propability,
+				true,
+			))
+		}
+	}
+
+	fn eval(&self, _hit: &Hit, _wo: Vector3, _wi: Vector3) -> Vector3 {
+		Vector3::new(0.0, 0.0, 0.0)
+	}
+
+	fn pdf(&self, _hit: &Hit, _wo: Vector3, _wi: Vector3) -> f32 {
+		0.0
+	}
+
+	fn is_specular(&self, _hit: &Hit) -> bool {
+		true
+	}
 }
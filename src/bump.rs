@@ -0,0 +1,109 @@
+use bvh::Vector3;
+use hit::Hit;
+use microfacet::reflect;
+use texture::Texture;
+use Vector2;
+
+// Side of the surface the shading normal faces, so perturbations are applied to the outward normal
+fn orientation(hit: &Hit) -> f32 {
+	if hit.n.dot(hit.ng) < 0.0 {
+		-1.0
+	} else {
+		1.0
+	}
+}
+
+// Bump mapping [Blinn 1978]: the surface is displaced along its normal by the first channel of the
+// height texture, and the normal of the displaced surface is rebuilt from finite differences
+pub fn bump_normal(height: &dyn Texture, scale: f32, hit: &Hit) -> Vector3 {
+	let du_length = hit.dpdu.length();
+	let dv_length = hit.dpdv.length();
+	if du_length == 0.0 || dv_length == 0.0 {
+		return hit.n;
+	}
+
+	let orientation = orientation(hit);
+	let n = hit.n * orientation;
+
+	// Offsets of about half the ray footprint, so the differences follow the filtered texture
+	let du = (0.5 * hit.footprint / du_length).max(1e-4);
+	let dv = (0.5 * hit.footprint / dv_length).max(1e-4);
+
+	let mut shifted = hit.clone();
+	let h = height.evaluate(hit).x;
+
+	shifted.p = hit.p + hit.dpdu * du;
+	shifted.uv = hit.uv + Vector2::new(du, 0.0);
+	let dhdu = (height.evaluate(&shifted).x - h) / du;
+
+	shifted.p = hit.p + hit.dpdv * dv;
+	shifted.uv = hit.uv + Vector2::new(0.0, dv);
+	let dhdv = (height.evaluate(&shifted).x - h) / dv;
+
+	// The tangents may be left-handed with respect to the normal
+	let handedness = if hit.dpdu.cross(hit.dpdv).dot(n) < 0.0 {
+		-1.0
+	} else {
+		1.0
+	};
+
+	let dpdu = hit.dpdu + n * (dhdu * scale);
+	let dpdv = hit.dpdv + n * (dhdv * scale);
+	let bumped = dpdu.cross(dpdv).normalize() * handedness;
+
+	if bumped.is_finite() {
+		bumped * orientation
+	} else {
+		hit.n
+	}
+}
+
+// Tangent space normal mapping, with the tangent frame built from the surface derivatives. The texture
+// stores normals in [0, 1], with green pointing along increasing v.
+pub fn map_normal(normals: &dyn Texture, hit: &Hit) -> Vector3 {
+	let orientation = orientation(hit);
+	let n = hit.n * orientation;
+
+	let tangent = (hit.dpdu - n * n.dot(hit.dpdu)).normalize();
+	if !tangent.is_finite() {
+		return hit.n;
+	}
+
+	let mut bitangent = n.cross(tangent);
+	if bitangent.dot(hit.dpdv) < 0.0 {
+		bitangent = -bitangent;
+	}
+
+	let value = normals.evaluate(hit) * 2.0 - Vector3::new(1.0, 1.0, 1.0);
+	let mapped = (tangent * value.x + bitangent * value.y + n * value.z).normalize();
+
+	if mapped.is_finite() {
+		mapped * orientation
+	} else {
+		hit.n
+	}
+}
+
+// Shading normals may face away from the viewer, or reflect it below the surface, which leaks light or
+// leaves black pixels. The normal is bent just enough for the mirror reflection of the view direction to
+// stay above the geometric surface [Keller et al. 2017].
+pub fn adapt_shading_normal(n: Vector3, ng: Vector3, wo: Vector3) -> Vector3 {
+	// Geometric normal on the side of the viewer
+	let ng = if ng.dot(wo) < 0.0 { -ng } else { ng };
+
+	let r = reflect(wo, n);
+	let height = r.dot(ng);
+	let min_height = 0.01 * wo.dot(ng);
+	if height >= min_height {
+		return n;
+	}
+
+	let r = (r + ng * (min_height - height)).normalize();
+	let adapted = (wo + r).normalize();
+
+	if adapted.is_finite() {
+		adapted
+	} else {
+		ng
+	}
+}
@@ -0,0 +1,158 @@
+use bsdf::{fresnel_schlick, BSDFSample, BSDF};
+use bvh::Vector3;
+use hit::Hit;
+use luminance;
+use microfacet::{reflect, GGX};
+use sampling::sample_cosine_hemisphere;
+use std::f32::consts::PI;
+
+// Diffuse base under a smooth or rough dielectric clear coat (ie: plastic, varnished wood).
+// Light refracted into the coat bounces between the base and the underside of the interface,
+// which is accounted for with the internal diffuse Fresnel reflectance.
+#[derive(Copy, Clone, Debug)]
+pub struct Coated {
+	pub distribution: GGX,
+	pub ior: f32,
+	// Fraction of diffuse light reflected back by the inside of the coat
+	internal_reflectance: f32,
+}
+
+impl Coated {
+	pub fn new(ior: f32, roughness: f32) -> Coated {
+		// Cosine-weighted hemispherical average of Schlick's approximation, seen from the outside,
+		// and the matching inside reflectance from reciprocity
+		let a = (ior - 1.0) / (ior + 1.0);
+		let base_reflectance = a * a;
+		let external_reflectance = base_reflectance + (1.0 - base_reflectance) / 21.0;
+
+		Coated {
+			distribution: GGX::from_roughness(roughness, 0.0),
+			ior,
+			internal_reflectance: 1.0 - (1.0 - external_reflectance) / (ior * ior),
+		}
+	}
+
+	pub fn plastic(roughness: f32) -> Coated {
+		Coated::new(1.5, roughness)
+	}
+
+	pub fn varnish(roughness: f32) -> Coated {
+		Coated::new(1.55, roughness)
+	}
+
+	// Probability of sampling the coat rather than the base, weighted by how much each reflects
+	fn specular_probability(&self, hit: &Hit, fresnel: f32) -> f32 {
+		let diffuse = (1.0 - fresnel) * luminance(hit.material.albedo);
+
+		if fresnel + diffuse > 0.0 {
+			fresnel / (fresnel + diffuse)
+		} else {
+			1.0
+		}
+	}
+
+	fn eval_diffuse(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		let albedo = hit.material.albedo;
+		let transmittance =
+			(1.0 - fresnel_schlick(wo.z, self.ior)) * (1.0 - fresnel_schlick(wi.z, self.ior));
+
+		// Sum of the internal bounces, per channel
+		let bounces = Vector3::new(
+			albedo.x / (1.0 - albedo.x * self.internal_reflectance),
+			albedo.y / (1.0 - albedo.y * self.internal_reflectance),
+			albedo.z / (1.0 - albedo.z * self.internal_reflectance),
+		);
+
+		bounces * (transmittance / (PI * self.ior * self.ior))
+	}
+
+	fn eval_specular(&self, distribution: &GGX, wo: Vector3, wi: Vector3) -> f32 {
+		let m = (wo + wi).normalize();
+		let fresnel = fresnel_schlick(wo.dot(m), self.ior);
+
+		fresnel * distribution.d(m) * distribution.g(wo, wi) / (4.0 * wo.z * wi.z)
+	}
+
+	fn pdf_specular(distribution: &GGX, wo: Vector3, wi: Vector3) -> f32 {
+		let m = (wo + wi).normalize();
+		distribution.pdf_visible(wo, m) / (4.0 * wo.dot(m))
+	}
+}
+
+impl BSDF for Coated {
+	fn sample(&self, hit: &Hit, wo: Vector3, uc: f32, u1: f32, u2: f32) -> Option<BSDFSample> {
+		if wo.z <= 0.0 {
+			return None;
+		}
+
+		let distribution = self.distribution.scaled(hit.material.roughness);
+		let specular_probability = self.specular_probability(hit, fresnel_schlick(wo.z, self.ior));
+
+		let wi = if uc < specular_probability {
+			if distribution.is_smooth() {
+				let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+				let fresnel = fresnel_schlick(wo.z, self.ior);
+				let weight = fresnel / specular_probability;
+
+				return Some(BSDFSample::new(
+					wi,
+					Vector3::new(weight, weight, weight),
+					specular_probability,
+					true,
+				));
+			}
+
+			reflect(wo, distribution.sample_visible(wo, u1, u2))
+		} else {
+			sample_cosine_hemisphere(u1, u2)
+		};
+
+		if wi.z <= 0.0 {
+			return None;
+		}
+
+		let pdf = self.pdf(hit, wo, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		Some(BSDFSample::new(
+			wi,
+			self.eval(hit, wo, wi) * (wi.z / pdf),
+			pdf,
+			false,
+		))
+	}
+
+	fn eval(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		if wo.z <= 0.0 || wi.z <= 0.0 {
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+
+		let distribution = self.distribution.scaled(hit.material.roughness);
+		let diffuse = self.eval_diffuse(hit, wo, wi);
+
+		if distribution.is_smooth() {
+			diffuse
+		} else {
+			let specular = self.eval_specular(&distribution, wo, wi);
+			diffuse + Vector3::new(specular, specular, specular)
+		}
+	}
+
+	fn pdf(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> f32 {
+		if wo.z <= 0.0 || wi.z <= 0.0 {
+			return 0.0;
+		}
+
+		let distribution = self.distribution.scaled(hit.material.roughness);
+		let specular_probability = self.specular_probability(hit, fresnel_schlick(wo.z, self.ior));
+		let diffuse = (1.0 - specular_probability) * wi.z / PI;
+
+		if distribution.is_smooth() {
+			diffuse
+		} else {
+			diffuse + specular_probability * Coated::pdf_specular(&distribution, wo, wi)
+		}
+	}
+}
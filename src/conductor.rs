@@ -0,0 +1,166 @@
+use bsdf::{BSDFSample, BSDF};
+use bvh::Vector3;
+use hit::Hit;
+use microfacet::{fresnel_conductor_rgb, reflect, GGX};
+
+// Rough metal, using a GGX microfacet distribution and a complex index of refraction per RGB channel
+#[derive(Copy, Clone, Debug)]
+pub struct Conductor {
+	pub distribution: GGX,
+	pub eta: Vector3,
+	pub k: Vector3,
+}
+
+impl Conductor {
+	pub fn new(eta: Vector3, k: Vector3, roughness: f32, anisotropy: f32) -> Conductor {
+		Conductor {
+			distribution: GGX::from_roughness(roughness, anisotropy),
+			eta,
+			k,
+		}
+	}
+
+	pub fn gold(roughness: f32) -> Conductor {
+		Conductor::new(
+			Vector3::new(0.143, 0.374, 1.442),
+			Vector3::new(3.983, 2.385, 1.603),
+			roughness,
+			0.0,
+		)
+	}
+
+	pub fn silver(roughness: f32) -> Conductor {
+		Conductor::new(
+			Vector3::new(0.155, 0.117, 0.138),
+			Vector3::new(4.828, 3.122, 2.147),
+			roughness,
+			0.0,
+		)
+	}
+
+	pub fn copper(roughness: f32) -> Conductor {
+		Conductor::new(
+			Vector3::new(0.200, 0.924, 1.102),
+			Vector3::new(3.912, 2.452, 2.142),
+			roughness,
+			0.0,
+		)
+	}
+
+	pub fn aluminium(roughness: f32) -> Conductor {
+		Conductor::new(
+			Vector3::new(1.657, 0.880, 0.521),
+			Vector3::new(9.224, 6.270, 4.837),
+			roughness,
+			0.0,
+		)
+	}
+
+	pub fn chromium(roughness: f32) -> Conductor {
+		Conductor::new(
+			Vector3::new(4.368, 2.910, 1.654),
+			Vector3::new(5.205, 4.231, 3.755),
+			roughness,
+			0.0,
+		)
+	}
+
+	pub fn iron(roughness: f32) -> Conductor {
+		Conductor::new(
+			Vector3::new(2.912, 2.950, 2.584),
+			Vector3::new(3.089, 2.932, 2.767),
+			roughness,
+			0.0,
+		)
+	}
+}
+
+impl BSDF for Conductor {
+	fn sample(&self, hit: &Hit, wo: Vector3, _uc: f32, u1: f32, u2: f32) -> Option<BSDFSample> {
+		if wo.z <= 0.0 {
+			return None;
+		}
+
+		let albedo = hit.material.albedo;
+		let distribution = self.distribution.scaled(hit.material.roughness);
+
+		if distribution.is_smooth() {
+			let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+			let fresnel = fresnel_conductor_rgb(wo.z, self.eta, self.k);
+
+			return Some(BSDFSample::new(wi, albedo * fresnel, 1.0, true));
+		}
+
+		// Sample a visible microfacet normal, and reflect around it
+		let m = distribution.sample_visible(wo, u1, u2);
+		let wi = reflect(wo, m);
+		if wi.z <= 0.0 {
+			return None;
+		}
+
+		let pdf = self.pdf(hit, wo, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		let fresnel = fresnel_conductor_rgb(wo.dot(m), self.eta, self.k);
+		let weight = albedo * fresnel * (distribution.g(wo, wi) / distribution.g1(wo));
+
+		Some(BSDFSample::new(wi, weight, pdf, false))
+	}
+
+	fn eval(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		let distribution = self.distribution.scaled(hit.material.roughness);
+
+		if wo.z <= 0.0 || wi.z <= 0.0 || distribution.is_smooth() {
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+
+		let m = (wo + wi).normalize();
+		let fresnel = fresnel_conductor_rgb(wo.dot(m), self.eta, self.k);
+		let d = distribution.d(m);
+		let g = distribution.g(wo, wi);
+
+		hit.material.albedo * fresnel * (d * g / (4.0 * wo.z * wi.z))
+	}
+
+	fn pdf(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> f32 {
+		let distribution = self.distribution.scaled(hit.material.roughness);
+
+		if wo.z <= 0.0 || wi.z <= 0.0 || distribution.is_smooth() {
+			return 0.0;
+		}
+
+		// Jacobian of the reflection mapping
+		let m = (wo + wi).normalize();
+		distribution.pdf_visible(wo, m) / (4.0 * wo.dot(m))
+	}
+
+	fn is_specular(&self, hit: &Hit) -> bool {
+		self.distribution.scaled(hit.material.roughness).is_smooth()
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn specular_follows_roughness_texture() {
+		let conductor = Conductor::gold(0.5);
+		let mut hit = Hit::init();
+
+		hit.material.roughness = 1.0;
+		assert!(!conductor.is_specular(&hit));
+
+		// A texture scaling the roughness down to zero makes the surface a mirror
+		hit.material.roughness = 0.0;
+		assert!(conductor.is_specular(&hit));
+		assert!(
+			conductor
+				.sample(&hit, Vector3::new(0.0, 0.6, 0.8), 0.5, 0.5, 0.5)
+				.unwrap()
+				.specular
+		);
+	}
+}
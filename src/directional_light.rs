@@ -0,0 +1,136 @@
+use bvh::Vector3;
+use light::{Incident, Light};
+use luminance;
+use sampling::sample_cone;
+use std::f32::consts::PI;
+
+// Distant light, such as the sun, arriving from a single direction or from a small disk of directions
+#[derive(Copy, Clone, Debug)]
+pub struct DirectionalLight {
+	// Direction the light travels in
+	pub direction: Vector3,
+	// Irradiance on a surface facing the light, in W/m²
+	pub irradiance: Vector3,
+	//
+	cos_angular_radius: f32,
+}
+
+impl DirectionalLight {
+	pub fn new(direction: Vector3, irradiance: Vector3) -> DirectionalLight {
+		DirectionalLight::with_angular_radius(direction, irradiance, 0.0)
+	}
+
+	// Soft shadows from a disk of uniform radiance, with the angular radius in radians (0.0047 for the sun)
+	pub fn with_angular_radius(
+		direction: Vector3,
+		irradiance: Vector3,
+		angular_radius: f32,
+	) -> DirectionalLight {
+		DirectionalLight {
+			direction: direction.normalize(),
+			irradiance,
+			cos_angular_radius: angular_radius.clamp(0.0, 0.5 * PI).cos(),
+		}
+	}
+}
+
+impl Light for DirectionalLight {
+	fn sample(&self, _origin: Vector3, u1: f32, u2: f32) -> Option<Incident> {
+		let axis = -self.direction;
+
+		if self.cos_angular_radius >= 1.0 {
+			return Some(Incident::new(axis, f32::INFINITY, self.irradiance, 1.0));
+		}
+
+		// A disk of radiance L subtends an irradiance of L π sin²θ at normal incidence
+		let sin_squared = 1.0 - self.cos_angular_radius * self.cos_angular_radius;
+		let radiance = self.irradiance / (PI * sin_squared);
+		let pdf = 1.0 / (2.0 * PI * (1.0 - self.cos_angular_radius));
+
+		Some(Incident::new(
+			sample_cone(axis, self.cos_angular_radius, u1, u2),
+			f32::INFINITY,
+			radiance,
+			pdf,
+		))
+	}
+
+	// Power falling onto a disk covering the scene
+	fn power(&self, scene_radius: f32) -> f32 {
+		PI * scene_radius * scene_radius * luminance(self.irradiance)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use material::Material;
+	use ray::Ray;
+	use scene::Scene;
+	use sphere::Sphere;
+
+	// Average of radiance * cos / pdf, over directions sampled by the light
+	fn irradiance(light: &DirectionalLight, origin: Vector3, normal: Vector3) -> Vector3 {
+		let n = 64;
+		let mut sum = Vector3::splat(0.0);
+		for i in 0..n {
+			for j in 0..n {
+				let (u1, u2) = ((i as f32 + 0.5) / n as f32, (j as f32 + 0.5) / n as f32);
+				let incident = light.sample(origin, u1, u2).unwrap();
+				assert_eq!(incident.distance, f32::INFINITY);
+				sum += incident.radiance * (incident.wi.dot(normal).max(0.0) / incident.pdf);
+			}
+		}
+		sum / (n * n) as f32
+	}
+
+	#[test]
+	fn constant_irradiance() {
+		let irradiance_at_normal = Vector3::new(3.0, 2.0, 1.0);
+		let direction = Vector3::new(0.0, -1.0, 0.0);
+		let up = Vector3::new(0.0, 1.0, 0.0);
+
+		for &angular_radius in [0.0, 0.0047, 0.2].iter() {
+			let light = DirectionalLight::with_angular_radius(
+				direction,
+				irradiance_at_normal,
+				angular_radius,
+			);
+			for &origin in [Vector3::splat(0.0), Vector3::new(100.0, -3.0, 50.0)].iter() {
+				let e = irradiance(&light, origin, up);
+				assert!(
+					(e - irradiance_at_normal).length() < 1e-3,
+					"{:?} at {:?}",
+					e,
+					origin
+				);
+			}
+		}
+	}
+
+	// Shadow rays towards the light, as cast by the integrator
+	#[test]
+	fn blocked_by_occluders() {
+		let mut scene = Scene::init();
+		scene.add(Box::new(Sphere::new(
+			1.0,
+			Vector3::new(0.0, 5.0, 0.0),
+			Material::white(),
+		)));
+
+		let light = DirectionalLight::with_angular_radius(
+			Vector3::new(0.0, -1.0, 0.0),
+			Vector3::splat(1.0),
+			0.01,
+		);
+		let occluded = |origin: Vector3| {
+			let incident = light.sample(origin, 0.3, 0.7).unwrap();
+			scene.occluded(Ray::new(origin, incident.wi), 1e-6, incident.distance)
+		};
+
+		assert!(occluded(Vector3::new(0.0, 0.0, 0.0)));
+		assert!(occluded(Vector3::new(0.3, -20.0, 0.1)));
+		assert!(!occluded(Vector3::new(2.0, 0.0, 0.0)));
+		assert!(!occluded(Vector3::new(0.0, 7.0, 0.0)));
+	}
+}
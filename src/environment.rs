@@ -0,0 +1,200 @@
+use bvh::Vector3;
+use image::ColorSpace;
+use light::{Incident, Light};
+use luminance;
+use sampling::Distribution2D;
+use std::f32::consts::PI;
+use std::io;
+use std::path::Path;
+use texture::Bitmap;
+use Vector2;
+
+// Latitude/longitude coordinates of a direction, with v going down from +y
+pub fn equirectangular_uv(direction: Vector3) -> Vector2 {
+	Vector2::new(
+		0.5 + direction.z.atan2(direction.x) / (2.0 * PI),
+		direction.y.clamp(-1.0, 1.0).acos() / PI,
+	)
+}
+
+pub fn equirectangular_direction(uv: Vector2) -> Vector3 {
+	let phi = (uv.x - 0.5) * 2.0 * PI;
+	let theta = uv.y * PI;
+
+	Vector3::new(
+		theta.sin() * phi.cos(),
+		theta.cos(),
+		theta.sin() * phi.sin(),
+	)
+}
+
+// Convert a density over the latitude/longitude coordinates to solid angle
+pub fn equirectangular_pdf(uv: Vector2, pdf: f32) -> f32 {
+	let sin_theta = (uv.y * PI).sin();
+	if sin_theta <= 0.0 {
+		return 0.0;
+	}
+
+	pdf / (2.0 * PI * PI * sin_theta)
+}
+
+// Distant light surrounding the scene, from an equirectangular (latitude/longitude) image with +y up.
+// Directions are importance sampled following the luminance of the texels.
+#[derive(Clone, Debug)]
+pub struct Environment {
+	// Rotation around the y axis, in radians
+	pub rotation: f32,
+	pub intensity: f32,
+	//
+	bitmap: Bitmap,
+	distribution: Distribution2D,
+	// Luminance integrated over all directions, before scaling by the intensity
+	integrated_luminance: f32,
+}
+
+impl Environment {
+	pub fn new(bitmap: Bitmap, rotation: f32, intensity: f32) -> Environment {
+		// The fields of a bitmap are public, so it may not have gone through Bitmap::new
+		assert!(bitmap.width > 0 && bitmap.height > 0, "empty environment");
+		assert_eq!(
+			bitmap.pixels.len(),
+			bitmap.width * bitmap.height,
+			"bitmap size mismatch"
+		);
+
+		// Rows near the poles cover a smaller solid angle
+		let mut weights = Vec::with_capacity(bitmap.pixels.len());
+		for y in 0..bitmap.height {
+			let sin_theta = (PI * (y as f32 + 0.5) / bitmap.height as f32).sin();
+			let row = &bitmap.pixels[y * bitmap.width..(y + 1) * bitmap.width];
+			weights.extend(
+				row.iter()
+					.map(|&texel| luminance(texel).max(0.0) * sin_theta),
+			);
+		}
+
+		let distribution = Distribution2D::new(&weights, bitmap.width, bitmap.height);
+		let texel_solid_angle = 2.0 * PI * PI / weights.len() as f32;
+		let integrated_luminance = weights.iter().sum::<f32>() * texel_solid_angle;
+
+		Environment {
+			rotation,
+			intensity,
+			bitmap,
+			distribution,
+			integrated_luminance,
+		}
+	}
+
+	// Load an HDR or PFM image
+	pub fn open<P: AsRef<Path>>(path: P, rotation: f32, intensity: f32) -> io::Result<Environment> {
+		let bitmap = Bitmap::open(path, ColorSpace::Linear)?;
+		Ok(Environment::new(bitmap, rotation, intensity))
+	}
+
+	// Image coordinates of a world space direction
+	fn direction_to_uv(&self, direction: Vector3) -> Vector2 {
+		let (sin, cos) = self.rotation.sin_cos();
+		let x = direction.x * cos - direction.z * sin;
+		let z = direction.x * sin + direction.z * cos;
+
+		equirectangular_uv(Vector3::new(x, direction.y, z))
+	}
+
+	fn uv_to_direction(&self, uv: Vector2) -> Vector3 {
+		let d = equirectangular_direction(uv);
+		let (sin, cos) = self.rotation.sin_cos();
+
+		Vector3::new(d.x * cos + d.z * sin, d.y, d.z * cos - d.x * sin)
+	}
+
+	fn lookup(&self, uv: Vector2) -> Vector3 {
+		let x = ((uv.x - uv.x.floor()) * self.bitmap.width as f32) as usize;
+		let y = (uv.y * self.bitmap.height as f32) as usize;
+
+		self.bitmap.pixels
+			[y.min(self.bitmap.height - 1) * self.bitmap.width + x.min(self.bitmap.width - 1)]
+			* self.intensity
+	}
+}
+
+impl Light for Environment {
+	fn sample(&self, _origin: Vector3, u1: f32, u2: f32) -> Option<Incident> {
+		let (uv, pdf) = self.distribution.sample(u1, u2);
+		let pdf = equirectangular_pdf(uv, pdf);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		Some(Incident::new(
+			self.uv_to_direction(uv),
+			f32::INFINITY,
+			self.lookup(uv),
+			pdf,
+		))
+	}
+
+	fn is_infinite(&self) -> bool {
+		true
+	}
+
+	fn emission(&self, direction: Vector3) -> Vector3 {
+		self.lookup(self.direction_to_uv(direction))
+	}
+
+	fn pdf(&self, _origin: Vector3, direction: Vector3) -> f32 {
+		let uv = self.direction_to_uv(direction);
+		equirectangular_pdf(uv, self.distribution.pdf(uv))
+	}
+
+	// Power falling onto a disk covering the scene
+	fn power(&self, scene_radius: f32) -> f32 {
+		PI * scene_radius * scene_radius * self.intensity * self.integrated_luminance
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn bitmap(width: usize, height: usize, pixels: Vec<Vector3>) -> Bitmap {
+		Bitmap {
+			width,
+			height,
+			pixels,
+		}
+	}
+
+	#[test]
+	#[should_panic(expected = "empty environment")]
+	fn empty_bitmap() {
+		Environment::new(bitmap(0, 0, vec![]), 0.0, 1.0);
+	}
+
+	#[test]
+	#[should_panic(expected = "empty environment")]
+	fn empty_rows() {
+		Environment::new(bitmap(4, 0, vec![]), 0.0, 1.0);
+	}
+
+	#[test]
+	#[should_panic(expected = "bitmap size mismatch")]
+	fn missing_pixels() {
+		Environment::new(bitmap(2, 2, vec![Vector3::splat(1.0)]), 0.0, 1.0);
+	}
+
+	#[test]
+	fn single_texel() {
+		let environment = Environment::new(bitmap(1, 1, vec![Vector3::splat(0.5)]), 0.3, 2.0);
+		let origin = Vector3::splat(0.0);
+		let incident = environment.sample(origin, 0.3, 0.6).unwrap();
+
+		assert_eq!(incident.radiance, Vector3::splat(1.0));
+		assert!((incident.pdf - environment.pdf(origin, incident.wi)).abs() < 1e-4 * incident.pdf);
+		assert_eq!(environment.emission(-incident.wi), Vector3::splat(1.0));
+
+		// The texel's luminance, weighted by the sine of its center latitude, over 2 pi^2 of uv solid angle
+		let power = PI * 2.0 * 0.5 * 2.0 * PI * PI;
+		assert!((environment.power(1.0) - power).abs() < 1e-3 * power);
+	}
+}
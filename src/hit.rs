@@ -1,14 +1,28 @@
 use bvh::Vector3;
 use material::Material;
-use std::f32::INFINITY;
+use Vector2;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Hit {
 	pub p: Vector3,
+	// Shading normal, flipped to face the incoming ray
 	pub n: Vector3,
+	// Geometric normal, always facing outwards
+	pub ng: Vector3,
 	pub t: f32,
 	pub b: Vector3,
+	// Surface coordinates, used for texturing
+	pub uv: Vector2,
+	// Partial derivatives of the position with respect to the surface coordinates
+	pub dpdu: Vector3,
+	pub dpdv: Vector3,
+	// Width of the ray cone at the hit point, used to filter textures
+	pub footprint: f32,
 	pub material: Material,
+	// Index of the hit object in Scene::objects, and of the hit triangle in Scene::triangles or in the
+	// mesh of a hit instance, filled in by Scene::intersect (usize::MAX otherwise)
+	pub object: usize,
+	pub primitive: usize,
 }
 
 impl Hit {
@@ -17,9 +31,16 @@ impl Hit {
 		Hit {
 			p,
 			n,
+			ng: n,
 			t,
 			b,
+			uv: Vector2::new(0.0, 0.0),
+			dpdu: Vector3::new(0.0, 0.0, 0.0),
+			dpdv: Vector3::new(0.0, 0.0, 0.0),
+			footprint: 0.0,
 			material,
+			object: usize::MAX,
+			primitive: usize::MAX,
 		}
 	}
 
@@ -27,9 +48,16 @@ impl Hit {
 		Hit {
 			p: Vector3::new(0.0, 0.0, 0.0),
 			n: Vector3::new(0.0, 0.0, 0.0),
-			t: INFINITY,
+			ng: Vector3::new(0.0, 0.0, 0.0),
+			t: f32::INFINITY,
 			b: Vector3::new(0.0, 0.0, 0.0),
+			uv: Vector2::new(0.0, 0.0),
+			dpdu: Vector3::new(0.0, 0.0, 0.0),
+			dpdv: Vector3::new(0.0, 0.0, 0.0),
+			footprint: 0.0,
 			material: Material::black(),
+			object: usize::MAX,
+			primitive: usize::MAX,
 		}
 	}
 }
@@ -0,0 +1,913 @@
+use bvh::Vector3;
+use hit::Hit;
+use png::{BitDepth, Decoder, Transformations};
+use std::fs;
+use std::io;
+use std::path::Path;
+use texture::{Bitmap, Texture};
+
+fn invalid(message: &str) -> io::Error {
+	io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+// Encoding of 8 and 16-bit images. Color maps are usually sRGB, while data maps (ie: roughness) are linear.
+// Floating point images (PFM, HDR) are always linear.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ColorSpace {
+	Linear,
+	Srgb,
+}
+
+pub fn srgb_to_linear(c: f32) -> f32 {
+	if c <= 0.04045 {
+		c / 12.92
+	} else {
+		((c + 0.055) / 1.055).powf(2.4)
+	}
+}
+
+impl Bitmap {
+	// Load a PNG, PPM, PFM or Radiance HDR image, picked from the file extension
+	pub fn open<P: AsRef<Path>>(path: P, color_space: ColorSpace) -> io::Result<Bitmap> {
+		let path = path.as_ref();
+		let data = fs::read(path)?;
+		let extension = path
+			.extension()
+			.and_then(|extension| extension.to_str())
+			.unwrap_or("")
+			.to_lowercase();
+
+		let mut bitmap = match extension.as_str() {
+			"png" => decode_png(&data)?,
+			"ppm" | "pgm" => decode_ppm(&data)?,
+			"pfm" => return decode_pfm(&data),
+			"hdr" => return decode_hdr(&data),
+			_ => return Err(invalid("unsupported image format")),
+		};
+
+		if color_space == ColorSpace::Srgb {
+			for pixel in &mut bitmap.pixels {
+				*pixel = Vector3::new(
+					srgb_to_linear(pixel.x),
+					srgb_to_linear(pixel.y),
+					srgb_to_linear(pixel.z),
+				);
+			}
+		}
+
+		Ok(bitmap)
+	}
+}
+
+// Whitespace separated header fields, skipping comments. Returns the fields, and the offset of the
+// data following the single whitespace character after the last field.
+fn header_fields(data: &[u8], count: usize) -> io::Result<(Vec<String>, usize)> {
+	let mut fields = vec![];
+	let mut i = 0;
+
+	while fields.len() < count {
+		match data.get(i) {
+			None => return Err(invalid("truncated image header")),
+			Some(b'#') => {
+				while i < data.len() && data[i] != b'\n' {
+					i += 1;
+				}
+			}
+			Some(c) if c.is_ascii_whitespace() => i += 1,
+			Some(_) => {
+				let start = i;
+				while i < data.len() && !data[i].is_ascii_whitespace() {
+					i += 1;
+				}
+				fields.push(String::from_utf8_lossy(&data[start..i]).into_owned());
+			}
+		}
+	}
+
+	Ok((fields, i + 1))
+}
+
+// Number of values making up an image, rejecting empty images and sizes that do not fit in memory
+fn value_count(width: usize, height: usize, values_per_pixel: usize) -> io::Result<usize> {
+	if width == 0 || height == 0 {
+		return Err(invalid("empty image"));
+	}
+
+	width
+		.checked_mul(height)
+		.and_then(|count| count.checked_mul(values_per_pixel))
+		.ok_or_else(|| invalid("image too large"))
+}
+
+// The `length` bytes of image data at `offset`, failing when the file is too short or the sizes overflow
+fn image_data(data: &[u8], offset: usize, length: usize) -> io::Result<&[u8]> {
+	offset
+		.checked_add(length)
+		.and_then(|end| data.get(offset..end))
+		.ok_or_else(|| invalid("truncated image data"))
+}
+
+fn parse<T: std::str::FromStr>(field: &str) -> io::Result<T> {
+	field.parse().map_err(|_| invalid("invalid image header"))
+}
+
+// Netpbm color (P3, P6) and grayscale (P2, P5) images, with 8 or 16-bit samples
+fn decode_ppm(data: &[u8]) -> io::Result<Bitmap> {
+	let (fields, offset) = header_fields(data, 4)?;
+	let width: usize = parse(&fields[1])?;
+	let height: usize = parse(&fields[2])?;
+	let max_value: u32 = parse(&fields[3])?;
+	if max_value == 0 || max_value > 65535 {
+		return Err(invalid("invalid netpbm maximum value"));
+	}
+
+	let channels = match fields[0].as_str() {
+		"P2" | "P5" => 1,
+		"P3" | "P6" => 3,
+		_ => return Err(invalid("unsupported netpbm format")),
+	};
+
+	let count = value_count(width, height, channels)?;
+	let samples: Vec<u32> = if fields[0] == "P2" || fields[0] == "P3" {
+		let (values, _) = header_fields(&data[offset..], count)?;
+		values
+			.iter()
+			.map(|value| parse(value))
+			.collect::<io::Result<_>>()?
+	} else if max_value < 256 {
+		let bytes = image_data(data, offset, count)?;
+		bytes.iter().map(|&byte| byte as u32).collect()
+	} else {
+		let length = count
+			.checked_mul(2)
+			.ok_or_else(|| invalid("image too large"))?;
+		image_data(data, offset, length)?
+			.chunks(2)
+			.map(|pair| (pair[0] as u32) << 8 | pair[1] as u32)
+			.collect()
+	};
+
+	let scale = 1.0 / max_value as f32;
+	let pixels = samples
+		.chunks(channels)
+		.map(|sample| {
+			if channels == 1 {
+				let value = sample[0] as f32 * scale;
+				Vector3::new(value, value, value)
+			} else {
+				Vector3::new(
+					sample[0] as f32 * scale,
+					sample[1] as f32 * scale,
+					sample[2] as f32 * scale,
+				)
+			}
+		})
+		.collect();
+
+	Ok(Bitmap::new(width, height, pixels))
+}
+
+// Portable float map, stored bottom row first
+fn decode_pfm(data: &[u8]) -> io::Result<Bitmap> {
+	let (fields, offset) = header_fields(data, 4)?;
+	let width: usize = parse(&fields[1])?;
+	let height: usize = parse(&fields[2])?;
+	let scale: f32 = parse(&fields[3])?;
+
+	let channels = match fields[0].as_str() {
+		"Pf" => 1,
+		"PF" => 3,
+		_ => return Err(invalid("unsupported float map format")),
+	};
+
+	let bytes = image_data(data, offset, value_count(width, height, channels * 4)?)?;
+	let values: Vec<f32> = bytes
+		.chunks(4)
+		.map(|b| {
+			let bytes = [b[0], b[1], b[2], b[3]];
+			if scale < 0.0 {
+				f32::from_le_bytes(bytes)
+			} else {
+				f32::from_be_bytes(bytes)
+			}
+		})
+		.collect();
+
+	let mut pixels = Vec::with_capacity(width * height);
+	for y in (0..height).rev() {
+		for x in 0..width {
+			let i = (y * width + x) * channels;
+			pixels.push(if channels == 1 {
+				Vector3::new(values[i], values[i], values[i])
+			} else {
+				Vector3::new(values[i], values[i + 1], values[i + 2])
+			});
+		}
+	}
+
+	Ok(Bitmap::new(width, height, pixels))
+}
+
+// Radiance RGBE image, with flat or run-length encoded scanlines
+fn decode_hdr(data: &[u8]) -> io::Result<Bitmap> {
+	if !data.starts_with(b"#?") {
+		return Err(invalid("invalid radiance header"));
+	}
+
+	// The header ends with an empty line
+	let mut i = 0;
+	loop {
+		let end = data[i..]
+			.iter()
+			.position(|&c| c == b'\n')
+			.ok_or_else(|| invalid("truncated radiance header"))?;
+		let line = &data[i..i + end];
+		i += end + 1;
+
+		if line.starts_with(b"FORMAT=") && line != b"FORMAT=32-bit_rle_rgbe" {
+			return Err(invalid("unsupported radiance pixel format"));
+		}
+		if line.is_empty() {
+			break;
+		}
+	}
+
+	let (fields, offset) = header_fields(&data[i..], 4)?;
+	if fields[0] != "-Y" || fields[2] != "+X" {
+		return Err(invalid("unsupported radiance image orientation"));
+	}
+	let height: usize = parse(&fields[1])?;
+	let width: usize = parse(&fields[3])?;
+	value_count(width, height, 4)?;
+	let mut position = i + offset;
+
+	let mut next = |count: usize| -> io::Result<&[u8]> {
+		let bytes = data
+			.get(position..position + count)
+			.ok_or_else(|| invalid("truncated image data"))?;
+		position += count;
+		Ok(bytes)
+	};
+
+	let mut pixels = Vec::with_capacity(width * height);
+	let mut scanline = vec![0u8; width * 4];
+
+	for _ in 0..height {
+		let header = next(4)?;
+		let encoded = (8..32768).contains(&width)
+			&& header[0] == 2
+			&& header[1] == 2
+			&& ((header[2] as usize) << 8 | header[3] as usize) == width;
+
+		if encoded {
+			// Each channel is run-length encoded separately
+			for channel in 0..4 {
+				let mut x = 0;
+				while x < width {
+					let count = next(1)?[0] as usize;
+					if count > 128 {
+						let count = count - 128;
+						let value = next(1)?[0];
+						if x + count > width {
+							return Err(invalid("invalid radiance scanline"));
+						}
+						for j in x..x + count {
+							scanline[j * 4 + channel] = value;
+						}
+						x += count;
+					} else {
+						if count == 0 || x + count > width {
+							return Err(invalid("invalid radiance scanline"));
+						}
+						for (j, &value) in next(count)?.iter().enumerate() {
+							scanline[(x + j) * 4 + channel] = value;
+						}
+						x += count;
+					}
+				}
+			}
+		} else {
+			scanline[..4].copy_from_slice(header);
+			scanline[4..].copy_from_slice(next(width * 4 - 4)?);
+		}
+
+		for rgbe in scanline.chunks(4) {
+			pixels.push(if rgbe[3] == 0 {
+				Vector3::new(0.0, 0.0, 0.0)
+			} else {
+				let scale = 2.0f32.powi(rgbe[3] as i32 - 136);
+				Vector3::new(rgbe[0] as f32, rgbe[1] as f32, rgbe[2] as f32) * scale
+			});
+		}
+	}
+
+	Ok(Bitmap::new(width, height, pixels))
+}
+
+// PNG, in any color type and bit depth. Alpha is ignored.
+fn decode_png(data: &[u8]) -> io::Result<Bitmap> {
+	let mut decoder = Decoder::new(data);
+	// Expand palettes and bit depths below 8 to 8-bit samples
+	decoder.set_transformations(Transformations::EXPAND);
+
+	let mut reader = decoder.read_info()?;
+	let mut buffer = vec![0; reader.output_buffer_size()];
+	let info = reader.next_frame(&mut buffer)?;
+	// Read up to the end chunk, checking the checksums of the chunks following the image data
+	reader.finish()?;
+
+	let (width, height) = (info.width as usize, info.height as usize);
+	let channels = info.color_type.samples();
+	let count = value_count(width, height, channels)?;
+
+	let samples: Vec<f32> = if info.bit_depth == BitDepth::Sixteen {
+		buffer[..count * 2]
+			.chunks(2)
+			.map(|pair| ((pair[0] as u32) << 8 | pair[1] as u32) as f32 / 65535.0)
+			.collect()
+	} else {
+		buffer[..count]
+			.iter()
+			.map(|&byte| byte as f32 / 255.0)
+			.collect()
+	};
+
+	let pixels = samples
+		.chunks(channels)
+		.map(|sample| {
+			if channels < 3 {
+				Vector3::new(sample[0], sample[0], sample[0])
+			} else {
+				Vector3::new(sample[0], sample[1], sample[2])
+			}
+		})
+		.collect();
+
+	Ok(Bitmap::new(width, height, pixels))
+}
+
+// How texture coordinates outside [0, 1] are handled
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+	Repeat,
+	Clamp,
+	Mirror,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Filter {
+	// Closest texel of the full resolution image
+	Nearest,
+	// Bilinear interpolation in the closest mip level
+	Bilinear,
+	// Bilinear interpolation in the two closest mip levels, blended together
+	Trilinear,
+}
+
+// Filtered image texture. The mip level is picked from the ray footprint at the hit point.
+#[derive(Clone, Debug)]
+pub struct ImageTexture {
+	pub wrap: WrapMode,
+	pub filter: Filter,
+	levels: Vec<Bitmap>,
+}
+
+impl ImageTexture {
+	pub fn new(bitmap: Bitmap, wrap: WrapMode, filter: Filter) -> ImageTexture {
+		let mut levels = vec![bitmap];
+
+		// Box filter each level down to a single texel
+		loop {
+			let level = &levels[levels.len() - 1];
+			if level.width == 1 && level.height == 1 {
+				break;
+			}
+
+			let width = (level.width / 2).max(1);
+			let height = (level.height / 2).max(1);
+			let mut pixels = Vec::with_capacity(width * height);
+
+			for y in 0..height {
+				for x in 0..width {
+					let x0 = (x * 2).min(level.width - 1);
+					let x1 = (x * 2 + 1).min(level.width - 1);
+					let y0 = (y * 2).min(level.height - 1);
+					let y1 = (y * 2 + 1).min(level.height - 1);
+
+					pixels.push(
+						(level.pixels[y0 * level.width + x0]
+							+ level.pixels[y0 * level.width + x1]
+							+ level.pixels[y1 * level.width + x0]
+							+ level.pixels[y1 * level.width + x1])
+							* 0.25,
+					);
+				}
+			}
+
+			levels.push(Bitmap::new(width, height, pixels));
+		}
+
+		ImageTexture {
+			wrap,
+			filter,
+			levels,
+		}
+	}
+
+	// Load an image, repeated and trilinearly filtered
+	pub fn open<P: AsRef<Path>>(path: P, color_space: ColorSpace) -> io::Result<ImageTexture> {
+		let bitmap = Bitmap::open(path, color_space)?;
+		Ok(ImageTexture::new(
+			bitmap,
+			WrapMode::Repeat,
+			Filter::Trilinear,
+		))
+	}
+
+	pub fn width(&self) -> usize {
+		self.levels[0].width
+	}
+
+	pub fn height(&self) -> usize {
+		self.levels[0].height
+	}
+
+	fn wrap(&self, i: i64, size: usize) -> usize {
+		let size = size as i64;
+
+		(match self.wrap {
+			WrapMode::Repeat => i.rem_euclid(size),
+			WrapMode::Clamp => i.clamp(0, size - 1),
+			WrapMode::Mirror => {
+				let i = i.rem_euclid(2 * size);
+				if i < size {
+					i
+				} else {
+					2 * size - 1 - i
+				}
+			}
+		}) as usize
+	}
+
+	fn texel(&self, level: &Bitmap, x: i64, y: i64) -> Vector3 {
+		level.pixels[self.wrap(y, level.height) * level.width + self.wrap(x, level.width)]
+	}
+
+	fn nearest(&self, level: &Bitmap, u: f32, v: f32) -> Vector3 {
+		let x = (u * level.width as f32).floor() as i64;
+		let y = ((1.0 - v) * level.height as f32).floor() as i64;
+
+		self.texel(level, x, y)
+	}
+
+	fn bilinear(&self, level: &Bitmap, u: f32, v: f32) -> Vector3 {
+		// Texel centers are at half-integer coordinates
+		let s = u * level.width as f32 - 0.5;
+		let t = (1.0 - v) * level.height as f32 - 0.5;
+		let x = s.floor();
+		let y = t.floor();
+		let fx = s - x;
+		let fy = t - y;
+		let (x, y) = (x as i64, y as i64);
+
+		let top = self.texel(level, x, y) * (1.0 - fx) + self.texel(level, x + 1, y) * fx;
+		let bottom =
+			self.texel(level, x, y + 1) * (1.0 - fx) + self.texel(level, x + 1, y + 1) * fx;
+
+		top * (1.0 - fy) + bottom * fy
+	}
+
+	// Mip level whose texels match the size of the ray footprint, using the surface tangents
+	// to convert it to texture space
+	fn level_of_detail(&self, hit: &Hit) -> f32 {
+		let du = hit.dpdu.length();
+		let dv = hit.dpdv.length();
+		if hit.footprint <= 0.0 || du == 0.0 || dv == 0.0 {
+			return 0.0;
+		}
+
+		let texels = hit.footprint * (self.width() as f32 / du).max(self.height() as f32 / dv);
+		texels.max(1.0).log2().min((self.levels.len() - 1) as f32)
+	}
+}
+
+impl Texture for ImageTexture {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let (u, v) = (hit.uv.x, hit.uv.y);
+
+		match self.filter {
+			Filter::Nearest => self.nearest(&self.levels[0], u, v),
+			Filter::Bilinear => {
+				let level = self.level_of_detail(hit).round() as usize;
+				self.bilinear(&self.levels[level], u, v)
+			}
+			Filter::Trilinear => {
+				let lod = self.level_of_detail(hit);
+				let level = lod.floor() as usize;
+				let fine = self.bilinear(&self.levels[level], u, v);
+
+				if level + 1 < self.levels.len() {
+					let t = lod - level as f32;
+					fine * (1.0 - t) + self.bilinear(&self.levels[level + 1], u, v) * t
+				} else {
+					fine
+				}
+			}
+		}
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// CRC-32 of the PNG specification, one byte at a time, to checksum the chunks of test images
+	fn crc32(data: &[u8]) -> u32 {
+		const TABLE: [u32; 256] = {
+			let mut table = [0; 256];
+			let mut n = 0;
+			while n < 256 {
+				let mut c = n as u32;
+				let mut k = 0;
+				while k < 8 {
+					c = if c & 1 != 0 {
+						0xedb8_8320 ^ (c >> 1)
+					} else {
+						c >> 1
+					};
+					k += 1;
+				}
+				table[n] = c;
+				n += 1;
+			}
+			table
+		};
+
+		!data.iter().fold(!0u32, |crc, &byte| {
+			TABLE[((crc ^ byte as u32) & 0xff) as usize] ^ (crc >> 8)
+		})
+	}
+
+	fn assert_pixels(bitmap: &Bitmap, width: usize, height: usize, expected: &[Vector3]) {
+		assert_eq!((bitmap.width, bitmap.height), (width, height));
+		for (pixel, expected) in bitmap.pixels.iter().zip(expected.iter()) {
+			assert!(
+				(*pixel - *expected).abs().max_element() < 1e-4,
+				"{:?} != {:?}",
+				pixel,
+				expected
+			);
+		}
+	}
+
+	fn chunk(png: &mut Vec<u8>, kind: &[u8], data: &[u8]) {
+		let mut body = kind.to_vec();
+		body.extend_from_slice(data);
+
+		png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+		png.extend_from_slice(&body);
+		png.extend_from_slice(&crc32(&body).to_be_bytes());
+	}
+
+	// zlib stream of stored blocks
+	fn zlib_stored(data: &[u8]) -> Vec<u8> {
+		let mut stream = vec![0x78, 0x01];
+		let blocks: Vec<&[u8]> = data.chunks(65535).collect();
+		for (i, block) in blocks.iter().enumerate() {
+			let length = block.len() as u16;
+			stream.push((i + 1 == blocks.len()) as u8);
+			stream.extend_from_slice(&length.to_le_bytes());
+			stream.extend_from_slice(&(!length).to_le_bytes());
+			stream.extend_from_slice(block);
+		}
+
+		let (mut a, mut b) = (1u32, 0u32);
+		for &byte in data {
+			a = (a + byte as u32) % 65521;
+			b = (b + a) % 65521;
+		}
+		stream.extend_from_slice(&(b << 16 | a).to_be_bytes());
+		stream
+	}
+
+	fn paeth(a: u8, b: u8, c: u8) -> u8 {
+		let p = a as i16 + b as i16 - c as i16;
+		let (pa, pb, pc) = (
+			(p - a as i16).abs(),
+			(p - b as i16).abs(),
+			(p - c as i16).abs(),
+		);
+		if pa <= pb && pa <= pc {
+			a
+		} else if pb <= pc {
+			b
+		} else {
+			c
+		}
+	}
+
+	// Filter each row of packed samples with the given filter type, prefixing it with the type
+	fn filter_rows(rows: &[Vec<u8>], filters: &[u8], bytes_per_pixel: usize) -> Vec<u8> {
+		let mut scanlines = vec![];
+		for (y, row) in rows.iter().enumerate() {
+			let previous = if y > 0 {
+				rows[y - 1].clone()
+			} else {
+				vec![0; row.len()]
+			};
+			scanlines.push(filters[y]);
+
+			for x in 0..row.len() {
+				let a = if x >= bytes_per_pixel {
+					row[x - bytes_per_pixel]
+				} else {
+					0
+				};
+				let b = previous[x];
+				let c = if x >= bytes_per_pixel {
+					previous[x - bytes_per_pixel]
+				} else {
+					0
+				};
+
+				let prediction = match filters[y] {
+					0 => 0,
+					1 => a,
+					2 => b,
+					3 => ((a as u16 + b as u16) / 2) as u8,
+					_ => paeth(a, b, c),
+				};
+				scanlines.push(row[x].wrapping_sub(prediction));
+			}
+		}
+		scanlines
+	}
+
+	struct Png {
+		width: u32,
+		height: u32,
+		bit_depth: u8,
+		color_type: u8,
+		palette: Vec<u8>,
+	}
+
+	impl Png {
+		fn new(width: u32, height: u32, bit_depth: u8, color_type: u8) -> Png {
+			Png {
+				width,
+				height,
+				bit_depth,
+				color_type,
+				palette: vec![],
+			}
+		}
+
+		fn encode(&self, scanlines: &[u8]) -> Vec<u8> {
+			let mut header = vec![];
+			header.extend_from_slice(&self.width.to_be_bytes());
+			header.extend_from_slice(&self.height.to_be_bytes());
+			header.extend_from_slice(&[self.bit_depth, self.color_type, 0, 0, 0]);
+
+			let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+			chunk(&mut png, b"IHDR", &header);
+			if !self.palette.is_empty() {
+				chunk(&mut png, b"PLTE", &self.palette);
+			}
+
+			// Split the image data across consecutive chunks, which are concatenated before decompressing
+			chunk(&mut png, b"tEXt", b"Comment\0ignored");
+			let compressed = zlib_stored(scanlines);
+			let (first, second) = compressed.split_at(compressed.len() / 2);
+			chunk(&mut png, b"IDAT", first);
+			chunk(&mut png, b"IDAT", second);
+			chunk(&mut png, b"IEND", &[]);
+			png
+		}
+	}
+
+	// 5 rows of RGB pixels, one per filter type
+	fn rgb_rows() -> Vec<Vec<u8>> {
+		(0..5)
+			.map(|y| {
+				(0..4 * 3)
+					.map(|i| ((y * 53 + i * 29 + i * i * 7) % 256) as u8)
+					.collect()
+			})
+			.collect()
+	}
+
+	#[test]
+	fn png_every_filter() {
+		let rows = rgb_rows();
+		let png = Png::new(4, 5, 8, 2).encode(&filter_rows(&rows, &[0, 1, 2, 3, 4], 3));
+
+		let expected: Vec<Vector3> = rows
+			.iter()
+			.flat_map(|row| {
+				row.chunks(3)
+					.map(|c| Vector3::new(c[0] as f32, c[1] as f32, c[2] as f32) / 255.0)
+					.collect::<Vec<_>>()
+			})
+			.collect();
+		assert_pixels(&decode_png(&png).unwrap(), 4, 5, &expected);
+	}
+
+	#[test]
+	fn png_rgba_ignores_alpha() {
+		let rows = vec![
+			vec![10, 20, 30, 255, 40, 50, 60, 0],
+			vec![70, 80, 90, 128, 100, 110, 120, 1],
+		];
+		let png = Png::new(2, 2, 8, 6).encode(&filter_rows(&rows, &[4, 4], 4));
+
+		let expected = [
+			Vector3::new(10.0, 20.0, 30.0) / 255.0,
+			Vector3::new(40.0, 50.0, 60.0) / 255.0,
+			Vector3::new(70.0, 80.0, 90.0) / 255.0,
+			Vector3::new(100.0, 110.0, 120.0) / 255.0,
+		];
+		assert_pixels(&decode_png(&png).unwrap(), 2, 2, &expected);
+	}
+
+	#[test]
+	fn png_palette() {
+		// 2-bit indices, packed four to a byte: 0, 1, 2 then 2, 1, 0
+		let rows = vec![vec![0b0001_1000], vec![0b1001_0000]];
+		let mut png = Png::new(3, 2, 2, 3);
+		png.palette = vec![255, 0, 0, 0, 255, 0, 0, 0, 255];
+
+		let red = Vector3::new(1.0, 0.0, 0.0);
+		let green = Vector3::new(0.0, 1.0, 0.0);
+		let blue = Vector3::new(0.0, 0.0, 1.0);
+		let bitmap = decode_png(&png.encode(&filter_rows(&rows, &[0, 2], 1))).unwrap();
+		assert_pixels(&bitmap, 3, 2, &[red, green, blue, blue, green, red]);
+
+		// Index 3 is past the end of the palette, and decodes as black
+		let rows = vec![vec![0b1100_0000], vec![0]];
+		let bitmap = decode_png(&png.encode(&filter_rows(&rows, &[0, 0], 1))).unwrap();
+		assert_eq!(bitmap.pixels[0], Vector3::new(0.0, 0.0, 0.0));
+	}
+
+	#[test]
+	fn png_16_bit() {
+		let rows = vec![vec![
+			0x00, 0x00, 0xff, 0xff, 0x80, 0x00, 0x12, 0x34, 0xfe, 0xdc, 0x00, 0x01,
+		]];
+		let png = Png::new(2, 1, 16, 2).encode(&filter_rows(&rows, &[1], 6));
+
+		let expected = [
+			Vector3::new(0.0, 65535.0, 32768.0) / 65535.0,
+			Vector3::new(4660.0, 65244.0, 1.0) / 65535.0,
+		];
+		assert_pixels(&decode_png(&png).unwrap(), 2, 1, &expected);
+
+		let rows = vec![vec![0xff, 0xff, 0x40, 0x00]];
+		let png = Png::new(2, 1, 16, 0).encode(&filter_rows(&rows, &[3], 2));
+		let gray = 16384.0 / 65535.0;
+		let expected = [Vector3::new(1.0, 1.0, 1.0), Vector3::new(gray, gray, gray)];
+		assert_pixels(&decode_png(&png).unwrap(), 2, 1, &expected);
+	}
+
+	#[test]
+	fn png_rejects_invalid_headers() {
+		let rows = vec![vec![0, 0, 0]];
+		assert!(decode_png(&Png::new(1, 1, 4, 2).encode(&filter_rows(&rows, &[0], 3))).is_err());
+		assert!(decode_png(&Png::new(1, 1, 16, 3).encode(&filter_rows(&rows, &[0], 3))).is_err());
+		assert!(decode_png(&Png::new(1, 1, 8, 5).encode(&filter_rows(&rows, &[0], 3))).is_err());
+		assert!(decode_png(&Png::new(0, 1, 8, 2).encode(&[0])).is_err());
+		assert!(decode_png(&Png::new(1, 0, 8, 2).encode(&[])).is_err());
+	}
+
+	#[test]
+	fn png_truncated_fails() {
+		let png = Png::new(4, 5, 8, 2).encode(&filter_rows(&rgb_rows(), &[0, 1, 2, 3, 4], 3));
+		for length in 0..png.len() {
+			assert!(decode_png(&png[..length]).is_err(), "length {}", length);
+		}
+	}
+
+	#[test]
+	fn png_corrupted_chunk_fails() {
+		let png = Png::new(4, 5, 8, 2).encode(&filter_rows(&rgb_rows(), &[0, 1, 2, 3, 4], 3));
+
+		// A flipped bit in the last pixel of the image data, and in the checksum of the end chunk
+		let mut corrupted = png.clone();
+		corrupted[png.len() - 50] ^= 1;
+		assert!(decode_png(&corrupted).is_err());
+		let mut corrupted = png.clone();
+		corrupted[png.len() - 1] ^= 1;
+		assert!(decode_png(&corrupted).is_err());
+	}
+
+	#[test]
+	fn ppm_binary_and_ascii() {
+		let expected = [Vector3::new(1.0, 0.0, 0.5), Vector3::new(0.0, 1.0, 0.25)];
+
+		let mut binary = b"P6\n# comment\n2 1\n4\n".to_vec();
+		binary.extend_from_slice(&[4, 0, 2, 0, 4, 1]);
+		assert_pixels(&decode_ppm(&binary).unwrap(), 2, 1, &expected);
+
+		let ascii = b"P3 2 1 4\n4 0 2\n0 4 1\n";
+		assert_pixels(&decode_ppm(ascii).unwrap(), 2, 1, &expected);
+
+		let wide = b"P5 1 1 65535\n\x80\x00";
+		let gray = 32768.0 / 65535.0;
+		assert_pixels(
+			&decode_ppm(wide).unwrap(),
+			1,
+			1,
+			&[Vector3::new(gray, gray, gray)],
+		);
+	}
+
+	#[test]
+	fn ppm_invalid_fails() {
+		assert!(decode_ppm(b"P6 2 1 255\n\x01\x02\x03").is_err());
+		assert!(decode_ppm(b"P6 0 1 255\n").is_err());
+		assert!(decode_ppm(b"P6 1 1 0\n\x00\x00\x00").is_err());
+		assert!(decode_ppm(b"P3 1 1 255\n1 2").is_err());
+		assert!(decode_ppm(b"P7 1 1 255\n\x00").is_err());
+	}
+
+	#[test]
+	fn huge_sizes_fail() {
+		// Sizes whose data would end past the largest address
+		let ppm = format!("P5\n{} 1\n255\n", usize::MAX);
+		assert!(decode_ppm(ppm.as_bytes()).is_err());
+		let ppm = format!("P5\n{} 1\n65535\n", usize::MAX / 2);
+		assert!(decode_ppm(ppm.as_bytes()).is_err());
+		let pfm = format!("Pf\n{} 1\n-1.0\n", usize::MAX / 4);
+		assert!(decode_pfm(pfm.as_bytes()).is_err());
+		let pfm = format!("PF\n{} {}\n-1.0\n", usize::MAX / 2, 3);
+		assert!(decode_pfm(pfm.as_bytes()).is_err());
+	}
+
+	#[test]
+	fn pfm_bottom_row_first() {
+		let mut pfm = b"PF\n1 2\n-1.0\n".to_vec();
+		for &value in [1.0f32, 2.0, 3.0, 4.0, 5.0, 6.0].iter() {
+			pfm.extend_from_slice(&value.to_le_bytes());
+		}
+		let expected = [Vector3::new(4.0, 5.0, 6.0), Vector3::new(1.0, 2.0, 3.0)];
+		assert_pixels(&decode_pfm(&pfm).unwrap(), 1, 2, &expected);
+
+		let mut pfm = b"Pf\n1 1\n1.0\n".to_vec();
+		pfm.extend_from_slice(&0.5f32.to_be_bytes());
+		assert_pixels(
+			&decode_pfm(&pfm).unwrap(),
+			1,
+			1,
+			&[Vector3::new(0.5, 0.5, 0.5)],
+		);
+
+		assert!(decode_pfm(b"PF\n1 1\n-1.0\n\x00\x00").is_err());
+		assert!(decode_pfm(b"PF\n0 0\n-1.0\n").is_err());
+	}
+
+	#[test]
+	fn hdr_flat_and_run_length_encoded() {
+		let header = b"#?RADIANCE\nFORMAT=32-bit_rle_rgbe\n\n";
+
+		// Flat scanlines: 128 with an exponent of 129 is 1
+		let mut flat = header.to_vec();
+		flat.extend_from_slice(b"-Y 1 +X 2\n");
+		flat.extend_from_slice(&[128, 64, 0, 129, 0, 0, 0, 0]);
+		let expected = [Vector3::new(1.0, 0.5, 0.0), Vector3::new(0.0, 0.0, 0.0)];
+		assert_pixels(&decode_hdr(&flat).unwrap(), 2, 1, &expected);
+
+		// Run-length encoded scanline of 8 pixels: a run for red, literals for green, and runs for blue
+		// and the exponent
+		let mut encoded = header.to_vec();
+		encoded.extend_from_slice(b"-Y 1 +X 8\n");
+		encoded.extend_from_slice(&[2, 2, 0, 8]);
+		encoded.extend_from_slice(&[136, 128]);
+		encoded.extend_from_slice(&[8, 0, 16, 32, 48, 64, 80, 96, 112]);
+		encoded.extend_from_slice(&[132, 0, 132, 64]);
+		encoded.extend_from_slice(&[136, 130]);
+
+		let expected: Vec<Vector3> = (0..8)
+			.map(|x| Vector3::new(128.0, x as f32 * 16.0, if x < 4 { 0.0 } else { 64.0 }) / 64.0)
+			.collect();
+		assert_pixels(&decode_hdr(&encoded).unwrap(), 8, 1, &expected);
+
+		for length in 0..encoded.len() {
+			assert!(decode_hdr(&encoded[..length]).is_err(), "length {}", length);
+		}
+	}
+
+	#[test]
+	fn hdr_invalid_fails() {
+		assert!(
+			decode_hdr(b"#?RADIANCE\nFORMAT=32-bit_rle_xyze\n\n-Y 1 +X 1\n\x00\x00\x00\x00")
+				.is_err()
+		);
+		assert!(decode_hdr(b"#?RADIANCE\n\n+Y 1 +X 1\n\x00\x00\x00\x00").is_err());
+		assert!(decode_hdr(b"#?RADIANCE\n\n-Y 0 +X 0\n").is_err());
+	}
+
+	#[test]
+	#[should_panic(expected = "empty bitmap")]
+	fn empty_bitmap_panics() {
+		Bitmap::new(0, 4, vec![]);
+	}
+}
@@ -0,0 +1,174 @@
+use bvh::aabb::AABB;
+use bvh::Vector3;
+use glam::{BVec3, Mat3};
+use hit::Hit;
+use material::Material;
+use mesh::Mesh;
+use ray::Ray;
+use std::sync::Arc;
+use PrimitiveType;
+use Traceable;
+use Transform;
+
+// Placement of a shared mesh in the scene, with an affine transform and optionally its own material.
+// Rays are brought into the space of the mesh to traverse its BVH, and hits are brought back out. Emissive
+// instances are only found by BSDF sampling, not picked as lights. Meshes must have at least one triangle.
+#[derive(Clone)]
+pub struct Instance {
+	pub mesh: Arc<Mesh>,
+	pub transform: Transform,
+	// Replaces the materials of the mesh triangles when set
+	pub material: Option<Material>,
+	//
+	inverse: Transform,
+	normal_matrix: Mat3,
+	bounds: AABB,
+}
+
+impl Instance {
+	pub fn new(mesh: Arc<Mesh>, transform: Transform) -> Instance {
+		assert!(!mesh.triangles.is_empty(), "instance of an empty mesh");
+		let inverse = transform.inverse();
+
+		// World bounds enclosing the transformed corners of the mesh bounds
+		let mesh_bounds = mesh.bounds();
+		let mut bounds = AABB::empty();
+		if !mesh_bounds.is_empty() {
+			for corner in 0..8 {
+				let mask = BVec3::new(corner & 1 != 0, corner & 2 != 0, corner & 4 != 0);
+				let p = Vector3::select(mask, mesh_bounds.max, mesh_bounds.min);
+				bounds.grow_mut(&transform.transform_point3(p));
+			}
+		}
+
+		Instance {
+			mesh,
+			transform,
+			material: None,
+			inverse,
+			normal_matrix: Mat3::from(inverse.matrix3).transpose(),
+			bounds,
+		}
+	}
+
+	pub fn with_material(mut self, material: Material) -> Instance {
+		self.material = Some(material);
+		self
+	}
+}
+
+impl Traceable for Instance {
+	fn intersect(&self, ray: &Ray, result: &mut Hit) -> bool {
+		// The direction is not normalized, so distances along the ray are the same in both spaces
+		let local = Ray::new(
+			self.inverse.transform_point3(ray.origin),
+			self.inverse.transform_vector3(ray.direction),
+		);
+
+		let index = match self.mesh.intersect(&local, result.t) {
+			Some((index, _)) => index,
+			None => return false,
+		};
+
+		if !self.mesh.triangles[index].intersect(&local, result) {
+			return false;
+		}
+
+		result.p = ray.origin + ray.direction * result.t;
+		result.ng = (self.normal_matrix * result.ng).normalize();
+		result.n = (self.normal_matrix * result.n).normalize();
+		result.dpdu = self.transform.transform_vector3(result.dpdu);
+		result.dpdv = self.transform.transform_vector3(result.dpdv);
+		result.primitive = index;
+
+		true
+	}
+
+	fn occludes(&self, ray: &Ray, t_min: f32, t_max: f32, _scratch: &mut Hit) -> bool {
+		let local = Ray::new(
+			self.inverse.transform_point3(ray.origin),
+			self.inverse.transform_vector3(ray.direction),
+		);
+
+		self.mesh.occluded(&local, t_min, t_max)
+	}
+
+	fn get_primitive_type(&self) -> PrimitiveType {
+		PrimitiveType::Instance
+	}
+
+	// Material override, or the material of the first triangle for meshes sharing one material
+	fn get_material(&self) -> &Material {
+		match self.material {
+			Some(ref material) => material,
+			None => &self.mesh.triangles[0].material,
+		}
+	}
+
+	fn material_at(&self, hit: &Hit) -> &Material {
+		match self.material {
+			Some(ref material) => material,
+			None => &self.mesh.triangles[hit.primitive].material,
+		}
+	}
+
+	fn bounds(&self) -> Option<AABB> {
+		Some(self.bounds)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bsdf::Diffuse;
+	use scene::Scene;
+	use triangle::Triangle;
+
+	// Unit square in the xy plane
+	fn square(material: Material) -> Arc<Mesh> {
+		let p = |x: f32, y: f32| Vector3::new(x, y, 0.0);
+		Arc::new(Mesh::new(vec![
+			Triangle::new(p(-1.0, -1.0), p(1.0, -1.0), p(1.0, 1.0), material.clone()),
+			Triangle::new(p(-1.0, -1.0), p(1.0, 1.0), p(-1.0, 1.0), material),
+		]))
+	}
+
+	#[test]
+	fn closest_of_overlapping_instances() {
+		let mesh = square(Material::white());
+		let mut scene = Scene::init();
+		for &z in [-3.0, -1.0, -2.0].iter() {
+			let transform = Transform::from_translation(Vector3::new(0.0, 0.0, z));
+			scene.add(Box::new(Instance::new(mesh.clone(), transform)));
+		}
+
+		let ray = Ray::new(Vector3::new(0.2, 0.1, 0.0), Vector3::new(0.0, 0.0, -1.0));
+		let hit = scene.intersect(ray).unwrap();
+		assert!((hit.t - 1.0).abs() < 1e-5);
+		assert_eq!(hit.object, 1);
+
+		// Parts of the mesh beyond the closest hit so far are skipped
+		let mut result = Hit::init();
+		result.t = 0.5;
+		assert!(!scene.objects[1].intersect(&ray, &mut result));
+	}
+
+	#[test]
+	fn emissive_instances_are_not_lights() {
+		let light = Material::new(
+			Vector3::new(1.0, 1.0, 1.0),
+			Vector3::new(0.0, 0.0, 0.0),
+			Diffuse,
+		);
+		let mut scene = Scene::init();
+		scene.add(Box::new(Instance::new(square(light), Transform::IDENTITY)));
+
+		assert_eq!(scene.num_lights(), 0);
+	}
+
+	#[test]
+	#[should_panic(expected = "instance of an empty mesh")]
+	fn empty_mesh_panics() {
+		Instance::new(Arc::new(Mesh::new(vec![])), Transform::IDENTITY);
+	}
+}
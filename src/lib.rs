@@ -4,7 +4,9 @@
 #[macro_use]
 extern crate log;
 extern crate bvh;
+extern crate glam;
 extern crate num_cpus;
+extern crate png;
 extern crate rand;
 extern crate rayon;
 
@@ -14,31 +16,78 @@ use std::f32::consts::PI;
 use std::path::PathBuf;
 use std::sync::atomic::{AtomicUsize, Ordering};
 
+pub mod acceleration;
 pub mod bsdf;
+pub mod bump;
 pub mod camera;
+pub mod coated;
+pub mod conductor;
+pub mod directional_light;
+pub mod environment;
 pub mod hit;
+pub mod image;
+pub mod instance;
+pub mod light;
+pub mod light_sampler;
 pub mod material;
+pub mod mesh;
+pub mod microfacet;
 pub mod plane;
+pub mod point_light;
+pub mod principled;
+pub mod procedural;
 pub mod ray;
 pub mod rectangle;
+pub mod rough_glass;
+pub mod sampling;
 pub mod scene;
+pub mod sky;
+pub mod spectrum;
 pub mod sphere;
+pub mod spot_light;
+pub mod texture;
 pub mod triangle;
 pub mod vector;
+pub mod wide_bvh;
 
+pub use acceleration::*;
 pub use bsdf::*;
+pub use bump::*;
 pub(crate) use bvh::*;
 pub use camera::*;
+pub use coated::*;
+pub use conductor::*;
+pub use directional_light::*;
+pub use environment::*;
 pub use hit::*;
+pub use image::*;
+pub use instance::*;
+pub use light::*;
+pub use light_sampler::*;
 pub use material::*;
+pub use mesh::*;
+pub use microfacet::*;
 pub use plane::*;
+pub use point_light::*;
+pub use principled::*;
+pub use procedural::*;
 pub use ray::*;
 pub use rectangle::*;
+pub use rough_glass::*;
+pub use sampling::*;
 pub use scene::*;
+pub use sky::*;
+pub use spectrum::*;
 pub use sphere::*;
+pub use spot_light::*;
+pub use texture::*;
 pub use triangle::*;
+pub use wide_bvh::*;
 
-use bvh::bvh::BVH;
+use bvh::aabb::AABB;
+
+pub type Vector2 = glam::Vec2;
+pub type Transform = glam::Affine3A;
 
 #[derive(Clone, Debug, PartialEq)]
 pub enum PrimitiveType {
@@ -46,11 +95,47 @@ pub enum PrimitiveType {
 	Plane = 1,
 	Rectangle = 2,
 	Sphere = 3,
+	Instance = 4,
 }
 
 pub trait Traceable: Send + Sync {
+	// Fill in `result` if the ray hits the shape. `result.t` comes in as the distance to the closest hit so
+	// far, which shapes made of many primitives use to skip those beyond it.
 	fn intersect(&self, ray: &Ray, result: &mut Hit) -> bool;
 	fn get_primitive_type(&self) -> PrimitiveType;
+	fn get_material(&self) -> &Material;
+
+	// Material at the hit point, for shapes made of parts with different materials
+	fn material_at(&self, _hit: &Hit) -> &Material {
+		self.get_material()
+	}
+
+	// Sample a point on the surface as seen from `origin`, for explicit light sampling.
+	// Shapes that cannot be sampled (ie: infinite planes) are never picked as lights.
+	fn sample(&self, _origin: Vector3, _u1: f32, _u2: f32) -> Option<LightSample> {
+		None
+	}
+
+	// Probability density of `sample` returning the hit point, with respect to solid angle at `origin`
+	fn pdf(&self, _origin: Vector3, _hit: &Hit) -> f32 {
+		0.0
+	}
+
+	// Whether the shape blocks the ray between `t_min` and `t_max`, for visibility queries. Hits are written
+	// to `scratch` along the way, for shapes that need a full intersection to tell.
+	fn occludes(&self, ray: &Ray, t_min: f32, t_max: f32, scratch: &mut Hit) -> bool {
+		self.intersect(ray, scratch) && scratch.t > t_min && scratch.t < t_max
+	}
+
+	// Bounding box, for shapes of finite extent
+	fn bounds(&self) -> Option<AABB> {
+		None
+	}
+
+	// Surface area, used to estimate the power of emitters
+	fn area(&self) -> f32 {
+		0.0
+	}
 }
 
 pub fn trace(
@@ -61,21 +146,49 @@ pub fn trace(
 	samples: u32,
 	backbuffer: &mut [Vector3],
 	rays: &mut usize,
+) {
+	trace_seeded(
+		scene,
+		camera,
+		width,
+		height,
+		samples,
+		backbuffer,
+		rays,
+		rand::random(),
+	);
+}
+
+// Same as `trace`, with the random numbers of each pixel drawn from a generator seeded with `seed` and the
+// pixel index, so that a given seed always renders the same image
+#[allow(clippy::too_many_arguments)]
+pub fn trace_seeded(
+	scene: &Scene,
+	camera: &Camera,
+	width: usize,
+	height: usize,
+	samples: u32,
+	backbuffer: &mut [Vector3],
+	rays: &mut usize,
+	seed: u64,
 ) {
 	let ray_count = AtomicUsize::new(0);
 	let inv_width = 1.0 / width as f32;
 	let inv_height = 1.0 / height as f32;
 	let inv_samples = 1.0 / samples as f32;
+	let pixel_spread = (camera.right * inv_width).length() / camera.forward.length();
 
 	// For each row of pixels
 	backbuffer
-		.par_chunks_mut(width as usize)
+		.par_chunks_mut(width)
 		.enumerate()
 		.for_each(|(j, row)| {
 			row.iter_mut().enumerate().for_each(|(i, output)| {
 				let mut radiance = Vector3::new(0.0, 0.0, 0.0);
 				let mut num_rays = 0;
-				let mut rng = thread_rng();
+				let pixel = (j * width + i) as u64;
+				let mut rng =
+					StdRng::seed_from_u64(seed ^ pixel.wrapping_mul(0x9e37_79b9_7f4a_7c15));
 
 				for _ in 0..samples {
 					let rnd_x: f32 = rng.gen();
@@ -86,13 +199,15 @@ pub fn trace(
 					// Compute V
 					let v = camera.forward + camera.right * dx - camera.up * dy;
 
-					// Spawn a ray
+					// Spawn a ray, covering the angle of a pixel
 					let ray = Ray {
 						origin: camera.origin + v * 10.0,
 						direction: v.normalize(),
+						cone_width: pixel_spread * v.length() * 10.0,
+						cone_spread: pixel_spread,
 					};
 
-					radiance += compute_radiance(ray, scene, 0, &mut num_rays);
+					radiance += compute_radiance(ray, scene, &mut rng, &mut num_rays);
 				}
 
 				ray_count.fetch_add(num_rays, Ordering::Relaxed);
@@ -107,138 +222,212 @@ fn luminance(color: Vector3) -> f32 {
 	0.299 * color.x + 0.587 * color.y + 0.114 * color.z
 }
 
-fn compute_radiance(ray: Ray, scene: &Scene, depth: i32, num_rays: &mut usize) -> Vector3 {
+// Estimate direct lighting at a vertex by sampling a point on a randomly picked emitter,
+// weighted against BSDF sampling with multiple importance sampling
+fn sample_lights<R: Rng>(
+	scene: &Scene,
+	hit: &Hit,
+	frame: &Frame,
+	wo: Vector3,
+	rng: &mut R,
+	num_rays: &mut usize,
+) -> Vector3 {
+	let black = Vector3::new(0.0, 0.0, 0.0);
+	let (index, probability) = match scene.sample_light(hit.p, rng.gen()) {
+		Some(light) => light,
+		None => return black,
+	};
+
+	let num_objects = scene.lights.len();
+	let num_sources = scene.light_sources.len();
+
+	let light: &dyn Traceable = if index < num_objects {
+		scene.objects[scene.lights[index]].as_ref()
+	} else if index < num_objects + num_sources {
+		let light = scene.light_sources[index - num_objects].as_ref();
+		return sample_light_source(scene, light, probability, hit, frame, wo, rng, num_rays);
+	} else {
+		&scene.triangles[scene.triangle_lights[index - num_objects - num_sources]]
+	};
+
+	let sample = match light.sample(hit.p, rng.gen(), rng.gen()) {
+		Some(sample) if sample.pdf > 0.0 => sample,
+		_ => return black,
+	};
+
+	let to_light = sample.p - hit.p;
+	let distance = to_light.length();
+	if distance < 1e-4 {
+		return black;
+	}
+
+	let wi = to_light / distance;
+	let wi_local = frame.to_local(wi);
+	let f = hit.material.bsdf.eval(hit, wo, wi_local);
+	if f == black {
+		return black;
+	}
+
+	// Shadow ray
 	*num_rays += 1;
-	let intersect: Option<Hit> = scene.intersect(ray);
-
-	match intersect {
-		None => Vector3::new(0.0, 0.0, 0.0),
-		Some(hit) => {
-			let position = ray.origin + ray.direction * hit.t;
-			let normal = hit.n;
-
-			let mut f = hit.material.albedo;
-			if depth > 3 {
-				if rand::random::<f32>() < luminance(f) && depth < 10 {
-					f = f / luminance(f);
-				} else {
-					return hit.material.emission;
-				}
-			}
+	if scene.occluded(Ray::new(hit.p, wi), 1e-6, distance * (1.0 - 1e-3)) {
+		return black;
+	}
 
-			let irradiance: Vector3 = match hit.material.bsdf {
-				// Diffuse Reflection
-				BSDF::Diffuse => {
-					// Sample cosine distribution and transform into world tangent space
-					let r1 = 2.0 * PI * rand::random::<f32>();
-					let r2 = rand::random::<f32>();
-					let r2s = r2.sqrt();
-					let w_up = if normal.x.abs() > 0.1 {
-						Vector3::new(0.0, 1.0, 0.0)
-					} else {
-						Vector3::new(1.0, 0.0, 0.0)
-					};
+	let light_pdf = sample.pdf * probability;
+	let bsdf_pdf = hit.material.bsdf.pdf(hit, wo, wi_local);
+	let weight = power_heuristic(light_pdf, bsdf_pdf);
+
+	// Textured emitters are evaluated at the sampled point
+	let material = light.get_material();
+	let emission = if material.emission_texture.is_some() {
+		let mut light_hit = Hit::init();
+		light_hit.p = sample.p;
+		light_hit.n = sample.n;
+		light_hit.ng = sample.n;
+		light_hit.uv = sample.uv;
+		material.emission_at(&light_hit)
+	} else {
+		material.emission
+	};
+
+	emission * f * (wi_local.z.abs() * weight / light_pdf)
+}
 
-					let tangent = normal.cross(w_up).normalize();
-					let bitangent = normal.cross(tangent).normalize();
-					let next_direction = tangent * r1.cos() * r2s
-						+ bitangent * r1.sin() * r2s
-						+ normal * (1.0 - r2).sqrt();
-
-					compute_radiance(
-						Ray::new(position, next_direction.normalize()),
-						scene,
-						depth + 1,
-						num_rays,
-					)
-				}
+// Direct lighting from a light without geometry. Only lights surrounding the scene can also be reached
+// by BSDF sampling, and are weighted against it.
+#[allow(clippy::too_many_arguments)]
+fn sample_light_source<R: Rng>(
+	scene: &Scene,
+	light: &dyn Light,
+	probability: f32,
+	hit: &Hit,
+	frame: &Frame,
+	wo: Vector3,
+	rng: &mut R,
+	num_rays: &mut usize,
+) -> Vector3 {
+	let black = Vector3::new(0.0, 0.0, 0.0);
+
+	let incident = match light.sample(hit.p, rng.gen(), rng.gen()) {
+		Some(incident) if incident.pdf > 0.0 => incident,
+		_ => return black,
+	};
+
+	let wi_local = frame.to_local(incident.wi);
+	let f = hit.material.bsdf.eval(hit, wo, wi_local);
+	if f == black {
+		return black;
+	}
 
-				// Mirror Reflection
-				BSDF::Mirror => {
-					let r = ray.direction.normalize()
-						- normal.normalize() * 2.0 * normal.dot(ray.direction);
-
-					compute_radiance(
-						Ray::new(position, r.normalize()),
-						scene,
-						depth + 1,
-						num_rays,
-					)
-				}
+	// Shadow ray
+	*num_rays += 1;
+	if scene.occluded(
+		Ray::new(hit.p, incident.wi),
+		1e-6,
+		incident.distance * (1.0 - 1e-3),
+	) {
+		return black;
+	}
+
+	let light_pdf = incident.pdf * probability;
+	let weight = if light.is_infinite() {
+		power_heuristic(light_pdf, hit.material.bsdf.pdf(hit, wo, wi_local))
+	} else {
+		1.0
+	};
+
+	incident.radiance * f * (wi_local.z.abs() * weight / light_pdf)
+}
 
-				// Glass / Translucent
-				BSDF::Glass => {
-					let r = ray.direction.normalize()
-						- normal.normalize() * 2.0 * normal.dot(ray.direction);
-					let reflection = Ray::new(position, r);
-
-					// Compute input-output IOR
-					let into = normal.dot(normal) > 0.0;
-					let nc = 1.0;
-					let nt = 1.5;
-					let nnt = if into { nc / nt } else { nt / nc };
-
-					// Compute fresnel
-					let ddn = ray.direction.dot(normal);
-					let cos2t = 1.0 - nnt * nnt * (1.0 - ddn * ddn);
-
-					if cos2t < 0.0 {
-						// Total internal reflection
-						compute_radiance(reflection, scene, depth + 1, num_rays)
-					} else {
-						let transmitted_dir = (ray.direction * nnt
-							- normal
-								* (if into { 1.0 } else { -1.0 } * (ddn * nnt + cos2t.sqrt())))
-						.normalize();
-						let transmitted_ray = Ray::new(position, transmitted_dir);
-
-						let a = nt - nc;
-						let b = nt + nc;
-						let base_reflectance = a * a / (b * b);
-						let c = 1.0
-							- if into {
-								-ddn
-							} else {
-								transmitted_dir.dot(normal)
-							};
-
-						let reflectance =
-							base_reflectance + (1.0 - base_reflectance) * c * c * c * c * c;
-						let transmittance = 1.0 - reflectance;
-						let rr_propability = 0.25 + 0.5 * reflectance;
-						let reflectance_propability = reflectance / rr_propability;
-						let transmittance_propability = transmittance / (1.0 - rr_propability);
-
-						if depth > 1 {
-							// Russian roulette between reflectance and transmittance
-							if rand::random::<f32>() < rr_propability {
-								compute_radiance(reflection, scene, depth + 1, num_rays)
-									* reflectance_propability
-							} else {
-								compute_radiance(transmitted_ray, scene, depth + 1, num_rays)
-									* transmittance_propability
-							}
-						} else {
-							compute_radiance(reflection, scene, depth + 1, num_rays) * reflectance
-								+ compute_radiance(transmitted_ray, scene, depth + 1, num_rays)
-									* transmittance
-						}
+fn compute_radiance<R: Rng>(ray: Ray, scene: &Scene, rng: &mut R, num_rays: &mut usize) -> Vector3 {
+	let mut ray = ray;
+	let mut radiance = Vector3::new(0.0, 0.0, 0.0);
+	let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+
+	// Emitters reached through a non-specular bounce are weighted against light sampling
+	let mut specular_bounce = true;
+	let mut bsdf_pdf = 0.0;
+	let mut depth = 0;
+
+	loop {
+		*num_rays += 1;
+		let hit = match scene.intersect(ray) {
+			Some(hit) => hit,
+			None => {
+				// Escaping rays pick up the lights surrounding the scene
+				for (index, light) in scene.light_sources.iter().enumerate() {
+					let emission = light.emission(ray.direction);
+
+					if specular_bounce {
+						radiance += throughput * emission;
+					} else if light.is_infinite() {
+						let index = scene.lights.len() + index;
+						let light_pdf = light.pdf(ray.origin, ray.direction)
+							* scene.light_probability(ray.origin, index);
+						radiance += throughput * emission * power_heuristic(bsdf_pdf, light_pdf);
 					}
 				}
-			};
+				break;
+			}
+		};
 
-			irradiance * f + hit.material.emission
+		if specular_bounce {
+			radiance += throughput * hit.material.emission;
+		} else {
+			let light_pdf = scene.light_pdf(ray.origin, &hit);
+			radiance += throughput * hit.material.emission * power_heuristic(bsdf_pdf, light_pdf);
 		}
+
+		// Russian roulette
+		if depth > 3 {
+			let p = luminance(hit.material.albedo);
+			if rng.gen::<f32>() < p && depth < 10 {
+				throughput /= p;
+			} else {
+				break;
+			}
+		}
+
+		let bsdf = &hit.material.bsdf;
+		let frame = Frame::from_tangent(hit.n, hit.dpdu);
+		let wo = frame.to_local(-ray.direction);
+
+		if !bsdf.is_specular(&hit) {
+			radiance += throughput * sample_lights(scene, &hit, &frame, wo, rng, num_rays);
+		}
+
+		let sample = match bsdf.sample(&hit, wo, rng.gen(), rng.gen(), rng.gen()) {
+			Some(sample) => sample,
+			None => break,
+		};
+
+		throughput *= sample.weight;
+		specular_bounce = sample.specular;
+		bsdf_pdf = sample.pdf;
+		// Specular bounces keep the ray cone tight. Other bounces widen it to roughly the solid angle
+		// covered by the sample, which picks coarser texture mip levels.
+		let cone_spread = if sample.specular {
+			ray.cone_spread
+		} else {
+			ray.cone_spread.max((1.0 / sample.pdf).sqrt())
+		};
+
+		ray = Ray::new(hit.p, frame.to_world(sample.wi).normalize())
+			.with_cone(hit.footprint, cone_spread);
+		depth += 1;
 	}
+
+	radiance
 }
 
 // todo: remove me later
 
 pub fn saturate(color: Vector3) -> Vector3 {
 	Vector3::new(
-		color.x.max(0.0).min(1.0),
-		color.y.max(0.0).min(1.0),
-		color.z.max(0.0).min(1.0),
+		color.x.clamp(0.0, 1.0),
+		color.y.clamp(0.0, 1.0),
+		color.z.clamp(0.0, 1.0),
 	)
 }
 
@@ -0,0 +1,77 @@
+use bvh::aabb::AABB;
+use bvh::Vector3;
+use Vector2;
+
+// A point sampled on the surface of an emitter, used for next-event estimation
+#[derive(Copy, Clone, Debug)]
+pub struct LightSample {
+	pub p: Vector3,
+	pub n: Vector3,
+	// Surface coordinates, to evaluate textured emission
+	pub uv: Vector2,
+	// Probability density, with respect to solid angle as seen from the shading point
+	pub pdf: f32,
+}
+
+impl LightSample {
+	pub fn new(p: Vector3, n: Vector3, uv: Vector2, pdf: f32) -> LightSample {
+		LightSample { p, n, uv, pdf }
+	}
+}
+
+// Illumination arriving at a shading point from a light source
+#[derive(Copy, Clone, Debug)]
+pub struct Incident {
+	// Direction towards the light, and distance to it (infinite for distant lights)
+	pub wi: Vector3,
+	pub distance: f32,
+	// Radiance arriving along `wi`. For lights located at a single point or direction, this is the
+	// irradiance they deliver, with a pdf of 1.
+	pub radiance: Vector3,
+	// Probability density, with respect to solid angle as seen from the shading point
+	pub pdf: f32,
+}
+
+impl Incident {
+	pub fn new(wi: Vector3, distance: f32, radiance: Vector3, pdf: f32) -> Incident {
+		Incident {
+			wi,
+			distance,
+			radiance,
+			pdf,
+		}
+	}
+}
+
+// Light sources without geometry, which rays never hit. They only contribute through explicit light
+// sampling.
+pub trait Light: Send + Sync {
+	fn sample(&self, origin: Vector3, u1: f32, u2: f32) -> Option<Incident>;
+
+	// Lights surrounding the scene are also seen by rays escaping it, and are weighted against BSDF
+	// sampling with multiple importance sampling
+	fn is_infinite(&self) -> bool {
+		false
+	}
+
+	// Radiance arriving along a ray that escapes the scene in `direction`
+	fn emission(&self, _direction: Vector3) -> Vector3 {
+		Vector3::new(0.0, 0.0, 0.0)
+	}
+
+	// Probability density of `sample` returning `direction`, with respect to solid angle
+	fn pdf(&self, _origin: Vector3, _direction: Vector3) -> f32 {
+		0.0
+	}
+
+	// Emitted power as luminance, used to pick lights. Lights at infinity estimate the power reaching
+	// a scene of the given radius.
+	fn power(&self, _scene_radius: f32) -> f32 {
+		0.0
+	}
+
+	// Extent of the light, or None for lights at infinity
+	fn bounds(&self) -> Option<AABB> {
+		None
+	}
+}
@@ -0,0 +1,419 @@
+use bvh::aabb::AABB;
+use bvh::Vector3;
+use sampling::Distribution1D;
+
+// Summary of a light, from which light samplers are built
+#[derive(Copy, Clone, Debug)]
+pub struct LightInfo {
+	// Emitted power, as luminance
+	pub power: f32,
+	// Extent of the light, or None for lights at infinity
+	pub bounds: Option<AABB>,
+}
+
+impl LightInfo {
+	pub fn new(power: f32, bounds: Option<AABB>) -> LightInfo {
+		LightInfo { power, bounds }
+	}
+}
+
+// Strategy picking the light to sample for next-event estimation. Lights are referred to by their index
+// in the list the sampler is built from.
+pub trait LightSampler: Send + Sync {
+	fn build(lights: &[LightInfo]) -> Self
+	where
+		Self: Sized;
+
+	// Pick a light for the shading point `p`, and return it with the probability of picking it
+	fn sample(&self, p: Vector3, u: f32) -> Option<(usize, f32)>;
+
+	// Probability of `sample` picking `light` for the shading point `p`
+	fn probability(&self, p: Vector3, light: usize) -> f32;
+}
+
+// Every light is equally likely
+#[derive(Clone, Debug)]
+pub struct UniformLightSampler {
+	num_lights: usize,
+}
+
+impl LightSampler for UniformLightSampler {
+	fn build(lights: &[LightInfo]) -> UniformLightSampler {
+		UniformLightSampler {
+			num_lights: lights.len(),
+		}
+	}
+
+	fn sample(&self, _p: Vector3, u: f32) -> Option<(usize, f32)> {
+		if self.num_lights == 0 {
+			return None;
+		}
+
+		let index = ((u * self.num_lights as f32) as usize).min(self.num_lights - 1);
+		Some((index, 1.0 / self.num_lights as f32))
+	}
+
+	fn probability(&self, _p: Vector3, light: usize) -> f32 {
+		if light < self.num_lights {
+			1.0 / self.num_lights as f32
+		} else {
+			0.0
+		}
+	}
+}
+
+// Lights are picked in proportion to their emitted power, regardless of the shading point
+#[derive(Clone, Debug)]
+pub struct PowerLightSampler {
+	distribution: Option<Distribution1D>,
+}
+
+impl LightSampler for PowerLightSampler {
+	fn build(lights: &[LightInfo]) -> PowerLightSampler {
+		if lights.is_empty() {
+			return PowerLightSampler { distribution: None };
+		}
+
+		PowerLightSampler {
+			distribution: Some(Distribution1D::new(
+				lights.iter().map(|light| light.power).collect(),
+			)),
+		}
+	}
+
+	fn sample(&self, _p: Vector3, u: f32) -> Option<(usize, f32)> {
+		let (index, probability) = self.distribution.as_ref()?.sample_discrete(u);
+
+		if probability > 0.0 {
+			Some((index, probability))
+		} else {
+			None
+		}
+	}
+
+	fn probability(&self, _p: Vector3, light: usize) -> f32 {
+		match self.distribution {
+			Some(ref distribution) if light < distribution.len() => distribution.probability(light),
+			_ => 0.0,
+		}
+	}
+}
+
+#[derive(Copy, Clone, Debug)]
+struct LightNode {
+	bounds: AABB,
+	power: f32,
+	parent: usize,
+	// Index of the second child for interior nodes (the first child directly follows its parent), or
+	// of the light for leaves
+	index: usize,
+	is_leaf: bool,
+}
+
+// Hierarchy of bounded lights [Conty Estevez and Kulla 2018]. Starting from the root, each step picks a
+// child in proportion to its estimated contribution at the shading point, from its power and distance.
+// Lights at infinity are kept out of the hierarchy, and are picked as often as the hierarchy as a whole.
+#[derive(Clone, Debug)]
+pub struct BVHLightSampler {
+	nodes: Vec<LightNode>,
+	infinite_lights: Vec<usize>,
+	// Leaf node of each bounded light, or usize::MAX for lights at infinity
+	leaves: Vec<usize>,
+}
+
+impl BVHLightSampler {
+	fn build_recursive(
+		&mut self,
+		lights: &[LightInfo],
+		indices: &mut [usize],
+		parent: usize,
+	) -> usize {
+		let node = self.nodes.len();
+
+		if indices.len() == 1 {
+			let light = indices[0];
+			self.nodes.push(LightNode {
+				bounds: lights[light].bounds.unwrap(),
+				power: lights[light].power,
+				parent,
+				index: light,
+				is_leaf: true,
+			});
+			self.leaves[light] = node;
+
+			return node;
+		}
+
+		// Split at the median of the light centers, along their widest axis
+		let mut centers = AABB::empty();
+		for &light in indices.iter() {
+			centers.grow_mut(&lights[light].bounds.unwrap().center());
+		}
+		let axis = centers.largest_axis();
+		indices.sort_by(|&a, &b| {
+			let a = lights[a].bounds.unwrap().center()[axis];
+			let b = lights[b].bounds.unwrap().center()[axis];
+			a.partial_cmp(&b).unwrap_or(std::cmp::Ordering::Equal)
+		});
+
+		self.nodes.push(LightNode {
+			bounds: AABB::empty(),
+			power: 0.0,
+			parent,
+			index: 0,
+			is_leaf: false,
+		});
+
+		let middle = indices.len() / 2;
+		let (left, right) = indices.split_at_mut(middle);
+		let first = self.build_recursive(lights, left, node);
+		let second = self.build_recursive(lights, right, node);
+
+		self.nodes[node].bounds = self.nodes[first].bounds.join(&self.nodes[second].bounds);
+		self.nodes[node].power = self.nodes[first].power + self.nodes[second].power;
+		self.nodes[node].index = second;
+
+		node
+	}
+
+	// Estimated contribution of a node at `p`: its power over the squared distance, which is clamped to
+	// the size of the node when `p` is close to or inside it
+	fn importance(&self, node: usize, p: Vector3) -> f32 {
+		let node = &self.nodes[node];
+		let size = node.bounds.size();
+		let to_center = node.bounds.center() - p;
+		let distance_squared = to_center.dot(to_center).max(0.25 * size.dot(size));
+
+		if distance_squared > 0.0 {
+			node.power / distance_squared
+		} else {
+			node.power
+		}
+	}
+
+	// Probability of picking each child of an interior node
+	fn child_probabilities(&self, node: usize, p: Vector3) -> Option<(f32, f32)> {
+		let first = self.importance(node + 1, p);
+		let second = self.importance(self.nodes[node].index, p);
+		let total = first + second;
+
+		if total > 0.0 && total.is_finite() {
+			Some((first / total, second / total))
+		} else {
+			None
+		}
+	}
+
+	// Probability of picking each of the lights at infinity
+	fn infinite_probability(&self) -> f32 {
+		if self.infinite_lights.is_empty() {
+			0.0
+		} else {
+			1.0 / (self.infinite_lights.len() + !self.nodes.is_empty() as usize) as f32
+		}
+	}
+
+	// Probability of picking the hierarchy rather than one of the lights at infinity
+	fn hierarchy_probability(&self) -> f32 {
+		if self.nodes.is_empty() {
+			0.0
+		} else {
+			1.0 / (1 + self.infinite_lights.len()) as f32
+		}
+	}
+}
+
+impl LightSampler for BVHLightSampler {
+	fn build(lights: &[LightInfo]) -> BVHLightSampler {
+		let mut sampler = BVHLightSampler {
+			nodes: vec![],
+			infinite_lights: vec![],
+			leaves: vec![usize::MAX; lights.len()],
+		};
+
+		let mut bounded = vec![];
+		for (index, light) in lights.iter().enumerate() {
+			if light.bounds.is_some() {
+				bounded.push(index);
+			} else {
+				sampler.infinite_lights.push(index);
+			}
+		}
+
+		if !bounded.is_empty() {
+			sampler.build_recursive(lights, &mut bounded, usize::MAX);
+		}
+
+		sampler
+	}
+
+	fn sample(&self, p: Vector3, u: f32) -> Option<(usize, f32)> {
+		let infinite_probability = self.infinite_probability();
+
+		// Lights at infinity come first
+		let infinite_share = self.infinite_lights.len() as f32 * infinite_probability;
+		if u < infinite_share {
+			let index = ((u / infinite_probability) as usize).min(self.infinite_lights.len() - 1);
+			return Some((self.infinite_lights[index], infinite_probability));
+		}
+
+		if self.nodes.is_empty() {
+			return None;
+		}
+
+		let mut u = ((u - infinite_share) / (1.0 - infinite_share)).clamp(0.0, 1.0 - f32::EPSILON);
+		let mut probability = self.hierarchy_probability();
+		let mut node = 0;
+
+		while !self.nodes[node].is_leaf {
+			let (first, second) = self.child_probabilities(node, p)?;
+
+			// Reuse the random number for the next step
+			if u < first {
+				u = (u / first).min(1.0 - f32::EPSILON);
+				probability *= first;
+				node += 1;
+			} else {
+				u = ((u - first) / second).clamp(0.0, 1.0 - f32::EPSILON);
+				probability *= second;
+				node = self.nodes[node].index;
+			}
+		}
+
+		if probability > 0.0 {
+			Some((self.nodes[node].index, probability))
+		} else {
+			None
+		}
+	}
+
+	fn probability(&self, p: Vector3, light: usize) -> f32 {
+		if light >= self.leaves.len() {
+			return 0.0;
+		}
+
+		let mut node = self.leaves[light];
+		if node == usize::MAX {
+			return self.infinite_probability();
+		}
+
+		// Walk up to the root, accumulating the probability of each step down
+		let mut probability = self.hierarchy_probability();
+		while self.nodes[node].parent != usize::MAX {
+			let parent = self.nodes[node].parent;
+			let (first, second) = match self.child_probabilities(parent, p) {
+				Some(probabilities) => probabilities,
+				None => return 0.0,
+			};
+
+			probability *= if node == parent + 1 { first } else { second };
+			node = parent;
+		}
+
+		probability
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::rngs::StdRng;
+	use rand::{Rng, SeedableRng};
+
+	const NUM_SAMPLES: usize = 20000;
+
+	fn random_lights(rng: &mut StdRng, bounded: usize, infinite: usize) -> Vec<LightInfo> {
+		let mut lights: Vec<LightInfo> = (0..bounded)
+			.map(|_| {
+				let p = Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+				let size = Vector3::new(rng.gen(), rng.gen(), rng.gen());
+				LightInfo::new(
+					rng.gen_range(0.1..10.0),
+					Some(AABB::with_bounds(p, p + size)),
+				)
+			})
+			.collect();
+		lights.extend((0..infinite).map(|_| LightInfo::new(rng.gen_range(0.1..10.0), None)));
+		lights
+	}
+
+	// At shading points around and among the lights: the probabilities of every light sum to 1, each
+	// sample comes with the probability of its light, and lights are picked that often
+	fn check<S: LightSampler>(lights: &[LightInfo]) {
+		let sampler = S::build(lights);
+		let mut rng = StdRng::seed_from_u64(5);
+
+		for _ in 0..10 {
+			let p = Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 14.0 - Vector3::splat(2.0);
+
+			let total: f32 = (0..lights.len()).map(|i| sampler.probability(p, i)).sum();
+			assert!((total - 1.0).abs() < 1e-4, "probabilities sum to {}", total);
+			assert_eq!(sampler.probability(p, lights.len()), 0.0);
+
+			let mut counts = vec![0; lights.len()];
+			for _ in 0..NUM_SAMPLES {
+				let (index, probability) = sampler.sample(p, rng.gen()).unwrap();
+				let expected = sampler.probability(p, index);
+				assert!(
+					(probability - expected).abs() <= 1e-4 * expected,
+					"light {} sampled with probability {} instead of {}",
+					index,
+					probability,
+					expected
+				);
+				counts[index] += 1;
+			}
+
+			for (index, &count) in counts.iter().enumerate() {
+				let expected = sampler.probability(p, index) * NUM_SAMPLES as f32;
+				assert!(
+					(count as f32 - expected).abs() < 5.0 * expected.sqrt() + 5.0,
+					"light {} picked {} times instead of {}",
+					index,
+					count,
+					expected
+				);
+			}
+		}
+	}
+
+	fn check_all(lights: &[LightInfo]) {
+		check::<UniformLightSampler>(lights);
+		check::<PowerLightSampler>(lights);
+		check::<BVHLightSampler>(lights);
+	}
+
+	#[test]
+	fn bounded_lights() {
+		let mut rng = StdRng::seed_from_u64(1);
+		for &count in [1, 2, 3, 50].iter() {
+			check_all(&random_lights(&mut rng, count, 0));
+		}
+	}
+
+	#[test]
+	fn lights_at_infinity() {
+		let mut rng = StdRng::seed_from_u64(2);
+		check_all(&random_lights(&mut rng, 0, 1));
+		check_all(&random_lights(&mut rng, 0, 3));
+		check_all(&random_lights(&mut rng, 20, 2));
+	}
+
+	#[test]
+	fn coincident_lights() {
+		let bounds = AABB::with_bounds(Vector3::splat(1.0), Vector3::splat(2.0));
+		let lights: Vec<LightInfo> = (0..8)
+			.map(|i| LightInfo::new(1.0 + i as f32, Some(bounds)))
+			.collect();
+		check_all(&lights);
+	}
+
+	#[test]
+	fn no_lights() {
+		let p = Vector3::splat(0.0);
+		assert!(UniformLightSampler::build(&[]).sample(p, 0.5).is_none());
+		assert!(PowerLightSampler::build(&[]).sample(p, 0.5).is_none());
+		assert!(BVHLightSampler::build(&[]).sample(p, 0.5).is_none());
+		assert_eq!(BVHLightSampler::build(&[]).probability(p, 0), 0.0);
+	}
+}
@@ -1,35 +1,184 @@
-use bsdf::BSDF;
+use bsdf::{Diffuse, BSDF};
+use bump::{bump_normal, map_normal};
 use bvh::Vector3;
+use hit::Hit;
+use principled::Principled;
+use spectrum::{blackbody, blackbody_efficacy};
+use std::f32::consts::PI;
+use std::sync::Arc;
+use texture::Texture;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Material {
 	pub emission: Vector3,
 	pub albedo: Vector3,
-	pub bsdf: BSDF,
+	pub bsdf: Arc<dyn BSDF>,
+	// Optional textures, modulating the constant parameters for each hit
+	pub emission_texture: Option<Arc<dyn Texture>>,
+	pub albedo_texture: Option<Arc<dyn Texture>>,
+	pub roughness_texture: Option<Arc<dyn Texture>>,
+	// Scale applied to the roughness of microfacet BSDFs, evaluated from the roughness texture
+	pub roughness: f32,
+	// Surface detail, perturbing the shading normal: a height texture scaled to world units, or a
+	// tangent space normal map
+	pub bump_texture: Option<Arc<dyn Texture>>,
+	pub bump_scale: f32,
+	pub normal_texture: Option<Arc<dyn Texture>>,
 }
 
 impl Material {
-	pub fn new(emission: Vector3, albedo: Vector3, bsdf: BSDF) -> Material {
+	pub fn new<T: BSDF + 'static>(emission: Vector3, albedo: Vector3, bsdf: T) -> Material {
 		Material {
 			emission,
 			albedo,
-			bsdf,
+			bsdf: Arc::new(bsdf),
+			emission_texture: None,
+			albedo_texture: None,
+			roughness_texture: None,
+			roughness: 1.0,
+			bump_texture: None,
+			bump_scale: 1.0,
+			normal_texture: None,
 		}
 	}
 
+	// Principled material, as authored in most content creation tools. All parameters are in [0, 1].
+	pub fn principled(
+		base_color: Vector3,
+		metallic: f32,
+		roughness: f32,
+		specular: f32,
+		sheen: f32,
+		clearcoat: f32,
+		transmission: f32,
+	) -> Material {
+		Material::new(
+			Vector3::new(0.0, 0.0, 0.0),
+			base_color,
+			Principled::new(
+				metallic,
+				roughness,
+				specular,
+				sheen,
+				clearcoat,
+				transmission,
+			),
+		)
+	}
+
 	pub fn black() -> Material {
-		Material {
-			emission: Vector3::new(0.0, 0.0, 0.0),
-			albedo: Vector3::new(0.0, 0.0, 0.0),
-			bsdf: BSDF::Diffuse,
-		}
+		Material::new(
+			Vector3::new(0.0, 0.0, 0.0),
+			Vector3::new(0.0, 0.0, 0.0),
+			Diffuse,
+		)
 	}
 
 	pub fn white() -> Material {
-		Material {
-			emission: Vector3::new(0.0, 0.0, 0.0),
-			albedo: Vector3::new(1.0, 1.0, 1.0),
-			bsdf: BSDF::Diffuse,
+		Material::new(
+			Vector3::new(0.0, 0.0, 0.0),
+			Vector3::new(1.0, 1.0, 1.0),
+			Diffuse,
+		)
+	}
+
+	// Emitter with the color of a blackbody at `temperature` kelvin, and the given luminance in cd/m²
+	pub fn blackbody(temperature: f32, luminance: f32) -> Material {
+		Material::new(
+			blackbody(temperature) * luminance,
+			Vector3::new(0.0, 0.0, 0.0),
+			Diffuse,
+		)
+	}
+
+	// Blackbody emitter of the given surface area in m², emitting `lumens` uniformly from one side
+	pub fn lumens(temperature: f32, lumens: f32, area: f32) -> Material {
+		let luminance = if area > 0.0 {
+			lumens / (PI * area)
+		} else {
+			0.0
+		};
+
+		Material::blackbody(temperature, luminance)
+	}
+
+	// Blackbody emitter of the given surface area in m², radiating `watts` from one side. The radiant power
+	// covers the whole spectrum, so hotter emitters look brighter for the same power.
+	pub fn watts(temperature: f32, watts: f32, area: f32) -> Material {
+		Material::lumens(temperature, watts * blackbody_efficacy(temperature), area)
+	}
+
+	pub fn with_emission_texture<T: Texture + 'static>(mut self, texture: T) -> Material {
+		self.emission_texture = Some(Arc::new(texture));
+		self
+	}
+
+	pub fn with_albedo_texture<T: Texture + 'static>(mut self, texture: T) -> Material {
+		self.albedo_texture = Some(Arc::new(texture));
+		self
+	}
+
+	// Only the first channel of the texture is used
+	pub fn with_roughness_texture<T: Texture + 'static>(mut self, texture: T) -> Material {
+		self.roughness_texture = Some(Arc::new(texture));
+		self
+	}
+
+	// Only the first channel of the texture is used, as a height scaled by `scale`
+	pub fn with_bump_texture<T: Texture + 'static>(mut self, texture: T, scale: f32) -> Material {
+		self.bump_texture = Some(Arc::new(texture));
+		self.bump_scale = scale;
+		self
+	}
+
+	// Normal maps are linear data, and should be loaded without sRGB conversion
+	pub fn with_normal_texture<T: Texture + 'static>(mut self, texture: T) -> Material {
+		self.normal_texture = Some(Arc::new(texture));
+		self
+	}
+
+	pub fn emission_at(&self, hit: &Hit) -> Vector3 {
+		match self.emission_texture {
+			Some(ref texture) => self.emission * texture.evaluate(hit),
+			None => self.emission,
+		}
+	}
+
+	pub fn albedo_at(&self, hit: &Hit) -> Vector3 {
+		match self.albedo_texture {
+			Some(ref texture) => self.albedo * texture.evaluate(hit),
+			None => self.albedo,
+		}
+	}
+
+	pub fn roughness_at(&self, hit: &Hit) -> f32 {
+		match self.roughness_texture {
+			Some(ref texture) => self.roughness * texture.evaluate(hit).x,
+			None => self.roughness,
 		}
 	}
+
+	// Shading normal at the hit, after bump and normal mapping
+	pub fn normal_at(&self, hit: &Hit) -> Vector3 {
+		let mut hit = hit.clone();
+
+		if let Some(ref texture) = self.normal_texture {
+			hit.n = map_normal(texture.as_ref(), &hit);
+		}
+
+		if let Some(ref texture) = self.bump_texture {
+			hit.n = bump_normal(texture.as_ref(), self.bump_scale, &hit);
+		}
+
+		hit.n
+	}
+
+	// Copy of the material with its textures evaluated at the hit point
+	pub fn at(&self, hit: &Hit) -> Material {
+		let mut material = self.clone();
+		material.emission = self.emission_at(hit);
+		material.albedo = self.albedo_at(hit);
+		material.roughness = self.roughness_at(hit);
+		material
+	}
 }
@@ -0,0 +1,53 @@
+use bvh::aabb::{Bounded, AABB};
+use ray::Ray;
+use triangle::Triangle;
+use wide_bvh::BVH8;
+
+// Triangles sharing one BVH, to be placed in the scene any number of times through instances
+pub struct Mesh {
+	pub triangles: Vec<Triangle>,
+	//
+	bvh: BVH8,
+}
+
+impl Mesh {
+	pub fn new(triangles: Vec<Triangle>) -> Mesh {
+		let bounds: Vec<AABB> = triangles.iter().map(|triangle| triangle.aabb()).collect();
+
+		Mesh {
+			bvh: BVH8::build(&bounds),
+			triangles,
+		}
+	}
+
+	pub fn bounds(&self) -> AABB {
+		self.bvh.bounds()
+	}
+
+	// Closest triangle the ray hits between 1e-6 and `t_max`, with the distance to the hit
+	pub fn intersect(&self, ray: &Ray, t_max: f32) -> Option<(usize, f32)> {
+		let mut closest = None;
+
+		self.bvh.intersect(ray, t_max, |index, t_max| {
+			let (t, _, _) = self.triangles[index].distance(ray)?;
+
+			if t > 1e-6 && t < t_max {
+				closest = Some((index, t));
+				Some(t)
+			} else {
+				None
+			}
+		});
+
+		closest
+	}
+
+	// Whether any triangle blocks the ray between `t_min` and `t_max`
+	pub fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+		self.bvh.occluded(
+			ray,
+			t_max,
+			|index| matches!(self.triangles[index].distance(ray), Some((t, _, _)) if t > t_min && t < t_max),
+		)
+	}
+}
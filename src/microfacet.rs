@@ -0,0 +1,151 @@
+use bvh::Vector3;
+use std::f32::consts::PI;
+
+// Anisotropic GGX / Trowbridge-Reitz microfacet distribution, in the local shading frame
+#[derive(Copy, Clone, Debug)]
+pub struct GGX {
+	pub alpha_x: f32,
+	pub alpha_y: f32,
+}
+
+impl GGX {
+	pub fn new(alpha_x: f32, alpha_y: f32) -> GGX {
+		GGX { alpha_x, alpha_y }
+	}
+
+	// Map a perceptual roughness and an anisotropy in [0, 1] to the distribution's alphas
+	pub fn from_roughness(roughness: f32, anisotropy: f32) -> GGX {
+		let alpha = roughness * roughness;
+		let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+
+		GGX::new(alpha / aspect, alpha * aspect)
+	}
+
+	// Same distribution, with the roughness multiplied by `scale` (ie: from a roughness texture)
+	pub fn scaled(&self, scale: f32) -> GGX {
+		let s = scale * scale;
+		GGX::new(self.alpha_x * s, self.alpha_y * s)
+	}
+
+	// Below this roughness, the distribution is treated as a perfectly specular surface
+	pub fn is_smooth(&self) -> bool {
+		self.alpha_x.max(self.alpha_y) < 1e-3
+	}
+
+	// Distribution of normals
+	pub fn d(&self, m: Vector3) -> f32 {
+		if m.z <= 0.0 {
+			return 0.0;
+		}
+
+		let x = m.x / self.alpha_x;
+		let y = m.y / self.alpha_y;
+		let e = x * x + y * y + m.z * m.z;
+
+		1.0 / (PI * self.alpha_x * self.alpha_y * e * e)
+	}
+
+	// Smith's auxiliary function
+	pub fn lambda(&self, w: Vector3) -> f32 {
+		let x = w.x * self.alpha_x;
+		let y = w.y * self.alpha_y;
+		let tan2_alpha2 = (x * x + y * y) / (w.z * w.z);
+
+		if !tan2_alpha2.is_finite() {
+			return f32::INFINITY;
+		}
+
+		((1.0 + tan2_alpha2).sqrt() - 1.0) * 0.5
+	}
+
+	// Smith masking
+	pub fn g1(&self, w: Vector3) -> f32 {
+		1.0 / (1.0 + self.lambda(w))
+	}
+
+	// Height-correlated Smith masking-shadowing
+	pub fn g(&self, wo: Vector3, wi: Vector3) -> f32 {
+		1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+	}
+
+	// Sample a normal from the distribution of normals visible from `wo` [Heitz 2018]
+	pub fn sample_visible(&self, wo: Vector3, u1: f32, u2: f32) -> Vector3 {
+		let flip = wo.z < 0.0;
+		let wo = if flip { -wo } else { wo };
+
+		// Transform the view direction to the hemisphere configuration
+		let vh = Vector3::new(self.alpha_x * wo.x, self.alpha_y * wo.y, wo.z).normalize();
+
+		// Orthonormal basis around the view direction
+		let len_sqrd = vh.x * vh.x + vh.y * vh.y;
+		let t1 = if len_sqrd > 0.0 {
+			Vector3::new(-vh.y, vh.x, 0.0) / len_sqrd.sqrt()
+		} else {
+			Vector3::new(1.0, 0.0, 0.0)
+		};
+		let t2 = vh.cross(t1);
+
+		// Sample the projected area
+		let r = u1.sqrt();
+		let phi = 2.0 * PI * u2;
+		let p1 = r * phi.cos();
+		let s = 0.5 * (1.0 + vh.z);
+		let p2 = (1.0 - s) * (1.0 - p1 * p1).max(0.0).sqrt() + s * r * phi.sin();
+
+		// Reproject onto the hemisphere, and back to the ellipsoid configuration
+		let nh = t1 * p1 + t2 * p2 + vh * (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt();
+		let m = Vector3::new(self.alpha_x * nh.x, self.alpha_y * nh.y, nh.z.max(1e-6)).normalize();
+
+		if flip {
+			-m
+		} else {
+			m
+		}
+	}
+
+	// Probability density of `sample_visible` returning `m`
+	pub fn pdf_visible(&self, wo: Vector3, m: Vector3) -> f32 {
+		// Normals are sampled on the side of `wo`, which never sees the back of a microfacet
+		let (wo, m) = if wo.z < 0.0 { (-wo, -m) } else { (wo, m) };
+
+		if wo.z == 0.0 || m.z <= 0.0 {
+			return 0.0;
+		}
+
+		self.g1(wo) * wo.dot(m).max(0.0) * self.d(m) / wo.z
+	}
+}
+
+pub fn reflect(wo: Vector3, m: Vector3) -> Vector3 {
+	m * (2.0 * wo.dot(m)) - wo
+}
+
+// Unpolarized Fresnel reflectance of a conductor with complex index of refraction eta + i k
+pub fn fresnel_conductor(cos_i: f32, eta: f32, k: f32) -> f32 {
+	let cos2 = cos_i * cos_i;
+	let sin2 = 1.0 - cos2;
+	let eta2 = eta * eta;
+	let k2 = k * k;
+
+	let t0 = eta2 - k2 - sin2;
+	let a2_plus_b2 = (t0 * t0 + 4.0 * eta2 * k2).sqrt();
+	let t1 = a2_plus_b2 + cos2;
+	let a = (0.5 * (a2_plus_b2 + t0)).max(0.0).sqrt();
+	let t2 = 2.0 * cos_i * a;
+	let rs = (t1 - t2) / (t1 + t2);
+
+	let t3 = cos2 * a2_plus_b2 + sin2 * sin2;
+	let t4 = t2 * sin2;
+	let rp = rs * (t3 - t4) / (t3 + t4);
+
+	0.5 * (rp + rs)
+}
+
+// Per-channel conductor Fresnel reflectance
+pub fn fresnel_conductor_rgb(cos_i: f32, eta: Vector3, k: Vector3) -> Vector3 {
+	Vector3::new(
+		fresnel_conductor(cos_i, eta.x, k.x),
+		fresnel_conductor(cos_i, eta.y, k.y),
+		fresnel_conductor(cos_i, eta.z, k.z),
+	)
+}
@@ -1,11 +1,13 @@
 use bvh::Vector3;
 use material::Material;
 use ray::Ray;
+use sampling::orthonormal_basis;
 use Hit;
 use PrimitiveType;
 use Traceable;
+use Vector2;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Plane {
 	pub position: Vector3,
 	pub normal: Vector3,
@@ -32,12 +34,19 @@ impl Traceable for Plane {
 		if denom > 1e-6 {
 			result.t = plane_normal.dot(self.position - r.origin) / denom;
 			result.p = r.origin + r.direction * result.t;
+			result.ng = self.normal;
+
+			// Planar mapping, in world units
+			let (tangent, bitangent) = orthonormal_basis(self.normal);
+			let v = result.p - self.position;
+			result.uv = Vector2::new(v.dot(tangent), v.dot(bitangent));
+			result.dpdu = tangent;
+			result.dpdv = bitangent;
 			result.n = if self.normal.dot(r.direction) < 0.0 {
 				self.normal
 			} else {
 				-self.normal
 			};
-			result.material = self.material;
 
 			true
 		} else {
@@ -48,4 +57,8 @@ impl Traceable for Plane {
 	fn get_primitive_type(&self) -> PrimitiveType {
 		PrimitiveType::Plane
 	}
+
+	fn get_material(&self) -> &Material {
+		&self.material
+	}
 }
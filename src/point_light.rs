@@ -0,0 +1,73 @@
+use bvh::aabb::AABB;
+use bvh::Vector3;
+use light::{Incident, Light};
+use luminance;
+use std::f32::consts::PI;
+
+// Infinitely small light emitting uniformly in all directions
+#[derive(Copy, Clone, Debug)]
+pub struct PointLight {
+	pub position: Vector3,
+	// Radiant intensity, in W/sr
+	pub intensity: Vector3,
+}
+
+impl PointLight {
+	pub fn new(position: Vector3, intensity: Vector3) -> PointLight {
+		PointLight {
+			position,
+			intensity,
+		}
+	}
+}
+
+impl Light for PointLight {
+	fn sample(&self, origin: Vector3, _u1: f32, _u2: f32) -> Option<Incident> {
+		let to_light = self.position - origin;
+		let distance_squared = to_light.dot(to_light);
+		if distance_squared == 0.0 {
+			return None;
+		}
+
+		let distance = distance_squared.sqrt();
+
+		Some(Incident::new(
+			to_light / distance,
+			distance,
+			self.intensity / distance_squared,
+			1.0,
+		))
+	}
+
+	fn power(&self, _scene_radius: f32) -> f32 {
+		4.0 * PI * luminance(self.intensity)
+	}
+
+	fn bounds(&self) -> Option<AABB> {
+		Some(AABB::with_bounds(self.position, self.position))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	#[test]
+	fn inverse_square_falloff() {
+		let light = PointLight::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(4.0, 2.0, 1.0));
+
+		let near = light.sample(Vector3::new(1.0, 2.0, 1.0), 0.5, 0.5).unwrap();
+		assert_eq!(near.wi, Vector3::new(0.0, 0.0, 1.0));
+		assert_eq!(near.distance, 2.0);
+		assert_eq!(near.radiance, Vector3::new(1.0, 0.5, 0.25));
+		assert_eq!(near.pdf, 1.0);
+
+		// Three times further away, in another direction
+		let far = light.sample(Vector3::new(1.0, 8.0, 3.0), 0.5, 0.5).unwrap();
+		assert_eq!(far.wi, Vector3::new(0.0, -1.0, 0.0));
+		assert_eq!(far.distance, 6.0);
+		assert!((far.radiance * 9.0 - near.radiance).length() < 1e-6);
+
+		assert!(light.sample(light.position, 0.5, 0.5).is_none());
+	}
+}
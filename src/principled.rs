@@ -0,0 +1,284 @@
+use bsdf::{fresnel_schlick, BSDFSample, BSDF};
+use bvh::Vector3;
+use hit::Hit;
+use luminance;
+use microfacet::{reflect, GGX};
+use rough_glass::RoughGlass;
+use sampling::sample_cosine_hemisphere;
+use std::f32::consts::PI;
+
+// Artist-facing principled BSDF [Burley 2012, 2015], with the base color taken from the material's albedo.
+// Metals and dielectrics share a GGX specular lobe, the dielectric part is split between a Burley diffuse
+// base (with sheen) and a rough glass lobe, and a clear coat is layered on top.
+#[derive(Copy, Clone, Debug)]
+pub struct Principled {
+	pub metallic: f32,
+	pub roughness: f32,
+	pub specular: f32,
+	pub sheen: f32,
+	pub clearcoat: f32,
+	pub transmission: f32,
+	//
+	distribution: GGX,
+	clearcoat_distribution: GGX,
+	glass: RoughGlass,
+}
+
+impl Principled {
+	pub fn new(
+		metallic: f32,
+		roughness: f32,
+		specular: f32,
+		sheen: f32,
+		clearcoat: f32,
+		transmission: f32,
+	) -> Principled {
+		let roughness = roughness.clamp(0.0, 1.0);
+
+		// The specular level maps [0, 1] to a normal incidence reflectance in [0, 0.08],
+		// 0.5 being a common dielectric with an IOR of 1.5
+		let base_reflectance = (0.08 * specular.clamp(0.0, 1.0)).sqrt();
+		let ior = ((1.0 + base_reflectance) / (1.0 - base_reflectance)).max(1.01);
+
+		Principled {
+			metallic: metallic.clamp(0.0, 1.0),
+			roughness,
+			specular: specular.clamp(0.0, 1.0),
+			sheen: sheen.clamp(0.0, 1.0),
+			clearcoat: clearcoat.clamp(0.0, 1.0),
+			transmission: transmission.clamp(0.0, 1.0),
+			distribution: GGX::from_roughness(roughness, 0.0),
+			clearcoat_distribution: GGX::from_roughness(0.1, 0.0),
+			glass: RoughGlass::new(ior, roughness),
+		}
+	}
+
+	fn diffuse_weight(&self) -> f32 {
+		(1.0 - self.metallic) * (1.0 - self.transmission)
+	}
+
+	fn glass_weight(&self) -> f32 {
+		(1.0 - self.metallic) * self.transmission
+	}
+
+	// Rays travelling inside a transmissive object only see the glass interface
+	fn is_inside(&self, hit: &Hit) -> bool {
+		self.transmission > 0.0 && hit.n.dot(hit.ng) < 0.0
+	}
+
+	// Reflectance of the shared specular lobe: Schlick's approximation tinted by the base color for
+	// metals, and the dielectric Fresnel for the opaque part
+	fn specular_fresnel(&self, base_color: Vector3, cos: f32) -> Vector3 {
+		let c = 1.0 - cos.clamp(0.0, 1.0);
+		let c5 = c * c * c * c * c;
+		let metal = base_color + (Vector3::new(1.0, 1.0, 1.0) - base_color) * c5;
+		let dielectric = self.diffuse_weight() * fresnel_schlick(cos, self.glass.ior);
+
+		metal * self.metallic + Vector3::new(dielectric, dielectric, dielectric)
+	}
+
+	fn clearcoat_fresnel(&self, cos: f32) -> f32 {
+		self.clearcoat * fresnel_schlick(cos, 1.5)
+	}
+
+	// Fraction of the light going through the clear coat in both directions, which is left for the lobes
+	// under it
+	fn clearcoat_transmittance(&self, wo: Vector3, wi: Vector3) -> f32 {
+		(1.0 - self.clearcoat_fresnel(wo.z.abs())) * (1.0 - self.clearcoat_fresnel(wi.z.abs()))
+	}
+
+	// Probabilities of sampling the diffuse, specular, glass and clear coat lobes, following their rough albedos
+	fn lobe_probabilities(&self, hit: &Hit, wo: Vector3) -> [f32; 4] {
+		let base_color = hit.material.albedo;
+		let weights = [
+			self.diffuse_weight() * luminance(base_color),
+			luminance(self.specular_fresnel(base_color, wo.z)),
+			self.glass_weight(),
+			self.clearcoat_fresnel(wo.z),
+		];
+
+		let total: f32 = weights.iter().sum();
+		if total <= 0.0 {
+			return [1.0, 0.0, 0.0, 0.0];
+		}
+
+		[
+			weights[0] / total,
+			weights[1] / total,
+			weights[2] / total,
+			weights[3] / total,
+		]
+	}
+
+	// Burley diffuse, with retro-reflection at grazing angles, and sheen. Only the light the dielectric
+	// specular lobe doesn't reflect reaches the base, both ways.
+	fn eval_diffuse(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		let base_color = hit.material.albedo;
+		let roughness = self.roughness * hit.material.roughness;
+		let cos_d = wi.dot((wo + wi).normalize());
+		let fd90 = 0.5 + 2.0 * roughness * cos_d * cos_d;
+		let fo = 1.0 + (fd90 - 1.0) * (1.0 - wo.z).powi(5);
+		let fi = 1.0 + (fd90 - 1.0) * (1.0 - wi.z).powi(5);
+		let sheen = self.sheen * (1.0 - cos_d).powi(5);
+		let transmittance = (1.0 - fresnel_schlick(wo.z, self.glass.ior))
+			* (1.0 - fresnel_schlick(wi.z, self.glass.ior));
+
+		(base_color * (fo * fi / PI) + Vector3::new(sheen, sheen, sheen))
+			* (self.diffuse_weight() * transmittance)
+	}
+
+	fn eval_specular(&self, hit: &Hit, distribution: &GGX, wo: Vector3, wi: Vector3) -> Vector3 {
+		let m = (wo + wi).normalize();
+		let d = distribution.d(m);
+		let g = distribution.g(wo, wi);
+
+		self.specular_fresnel(hit.material.albedo, wo.dot(m)) * (d * g / (4.0 * wo.z * wi.z))
+	}
+
+	fn eval_clearcoat(&self, wo: Vector3, wi: Vector3) -> f32 {
+		let m = (wo + wi).normalize();
+		let d = self.clearcoat_distribution.d(m);
+		let g = self.clearcoat_distribution.g(wo, wi);
+
+		self.clearcoat_fresnel(wo.dot(m)) * d * g / (4.0 * wo.z * wi.z)
+	}
+
+	fn pdf_reflection(distribution: &GGX, wo: Vector3, wi: Vector3) -> f32 {
+		let m = (wo + wi).normalize();
+		distribution.pdf_visible(wo, m) / (4.0 * wo.dot(m))
+	}
+}
+
+impl BSDF for Principled {
+	fn sample(&self, hit: &Hit, wo: Vector3, uc: f32, u1: f32, u2: f32) -> Option<BSDFSample> {
+		if self.is_inside(hit) {
+			return self.glass.sample(hit, wo, uc, u1, u2);
+		}
+
+		if wo.z <= 0.0 {
+			return None;
+		}
+
+		let distribution = self.distribution.scaled(hit.material.roughness);
+		let probabilities = self.lobe_probabilities(hit, wo);
+		let (diffuse, specular, glass) = (probabilities[0], probabilities[1], probabilities[2]);
+
+		// Reflection lobes may scatter below the horizon, where only the glass lobe has a density
+		let wi = if uc < diffuse {
+			sample_cosine_hemisphere(u1, u2)
+		} else if uc < diffuse + specular {
+			if distribution.is_smooth() {
+				let wi = Vector3::new(-wo.x, -wo.y, wo.z);
+				let weight = self.specular_fresnel(hit.material.albedo, wo.z)
+					* (self.clearcoat_transmittance(wo, wi) / specular);
+
+				return Some(BSDFSample::new(wi, weight, specular, true));
+			}
+
+			let wi = reflect(wo, distribution.sample_visible(wo, u1, u2));
+			if wi.z <= 0.0 {
+				return None;
+			}
+			wi
+		} else if uc < diffuse + specular + glass {
+			// Reuse the remainder of the lobe selection to pick between reflection and refraction
+			let uc = ((uc - diffuse - specular) / glass).min(1.0);
+			let sample = self.glass.sample(hit, wo, uc, u1, u2)?;
+
+			if sample.specular {
+				return Some(BSDFSample::new(
+					sample.wi,
+					sample.weight
+						* (self.glass_weight() * self.clearcoat_transmittance(wo, sample.wi)
+							/ glass),
+					sample.pdf * glass,
+					true,
+				));
+			}
+
+			sample.wi
+		} else {
+			let wi = reflect(wo, self.clearcoat_distribution.sample_visible(wo, u1, u2));
+			if wi.z <= 0.0 {
+				return None;
+			}
+			wi
+		};
+
+		if wi.z == 0.0 {
+			return None;
+		}
+
+		let pdf = self.pdf(hit, wo, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		Some(BSDFSample::new(
+			wi,
+			self.eval(hit, wo, wi) * (wi.z.abs() / pdf),
+			pdf,
+			false,
+		))
+	}
+
+	fn eval(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		if self.is_inside(hit) {
+			return self.glass.eval(hit, wo, wi);
+		}
+
+		let black = Vector3::new(0.0, 0.0, 0.0);
+		if wo.z <= 0.0 {
+			return black;
+		}
+
+		let mut f = self.glass.eval(hit, wo, wi) * self.glass_weight();
+		let mut clearcoat = 0.0;
+
+		if wi.z > 0.0 {
+			let distribution = self.distribution.scaled(hit.material.roughness);
+
+			f += self.eval_diffuse(hit, wo, wi);
+			if !distribution.is_smooth() {
+				f += self.eval_specular(hit, &distribution, wo, wi);
+			}
+
+			clearcoat = self.eval_clearcoat(wo, wi);
+		}
+
+		f * self.clearcoat_transmittance(wo, wi) + Vector3::new(clearcoat, clearcoat, clearcoat)
+	}
+
+	fn pdf(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> f32 {
+		if self.is_inside(hit) {
+			return self.glass.pdf(hit, wo, wi);
+		}
+
+		if wo.z <= 0.0 {
+			return 0.0;
+		}
+
+		let probabilities = self.lobe_probabilities(hit, wo);
+		let mut pdf = probabilities[2] * self.glass.pdf(hit, wo, wi);
+
+		if wi.z > 0.0 {
+			let distribution = self.distribution.scaled(hit.material.roughness);
+
+			pdf += probabilities[0] * wi.z / PI;
+			pdf +=
+				probabilities[3] * Principled::pdf_reflection(&self.clearcoat_distribution, wo, wi);
+
+			if !distribution.is_smooth() {
+				pdf += probabilities[1] * Principled::pdf_reflection(&distribution, wo, wi);
+			}
+		}
+
+		pdf
+	}
+
+	fn is_specular(&self, hit: &Hit) -> bool {
+		self.diffuse_weight() == 0.0
+			&& self.clearcoat == 0.0
+			&& self.distribution.scaled(hit.material.roughness).is_smooth()
+	}
+}
@@ -0,0 +1,373 @@
+use bvh::Vector3;
+use hit::Hit;
+use texture::Texture;
+
+// Coordinates procedural textures are evaluated from: the hit position in world space, or the surface
+// UVs (with z = 0)
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Mapping {
+	Position,
+	Uv,
+}
+
+impl Mapping {
+	fn point(&self, hit: &Hit, scale: f32) -> Vector3 {
+		match *self {
+			Mapping::Position => hit.p * scale,
+			Mapping::Uv => Vector3::new(hit.uv.x, hit.uv.y, 0.0) * scale,
+		}
+	}
+}
+
+// Ken Perlin's reference permutation
+const PERMUTATION: [u8; 256] = [
+	151, 160, 137, 91, 90, 15, 131, 13, 201, 95, 96, 53, 194, 233, 7, 225, 140, 36, 103, 30, 69,
+	142, 8, 99, 37, 240, 21, 10, 23, 190, 6, 148, 247, 120, 234, 75, 0, 26, 197, 62, 94, 252, 219,
+	203, 117, 35, 11, 32, 57, 177, 33, 88, 237, 149, 56, 87, 174, 20, 125, 136, 171, 168, 68, 175,
+	74, 165, 71, 134, 139, 48, 27, 166, 77, 146, 158, 231, 83, 111, 229, 122, 60, 211, 133, 230,
+	220, 105, 92, 41, 55, 46, 245, 40, 244, 102, 143, 54, 65, 25, 63, 161, 1, 216, 80, 73, 209, 76,
+	132, 187, 208, 89, 18, 169, 200, 196, 135, 130, 116, 188, 159, 86, 164, 100, 109, 198, 173,
+	186, 3, 64, 52, 217, 226, 250, 124, 123, 5, 202, 38, 147, 118, 126, 255, 82, 85, 212, 207, 206,
+	59, 227, 47, 16, 58, 17, 182, 189, 28, 42, 223, 183, 170, 213, 119, 248, 152, 2, 44, 154, 163,
+	70, 221, 153, 101, 155, 167, 43, 172, 9, 129, 22, 39, 253, 19, 98, 108, 110, 79, 113, 224, 232,
+	178, 185, 112, 104, 218, 246, 97, 228, 251, 34, 242, 193, 238, 210, 144, 12, 191, 179, 162,
+	241, 81, 51, 145, 235, 249, 14, 239, 107, 49, 192, 214, 31, 181, 199, 106, 157, 184, 84, 204,
+	176, 115, 121, 50, 45, 127, 4, 150, 254, 138, 236, 205, 93, 222, 114, 67, 29, 24, 72, 243, 141,
+	128, 195, 78, 66, 215, 61, 156, 180,
+];
+
+fn hash(i: i32) -> usize {
+	PERMUTATION[(i & 255) as usize] as usize
+}
+
+// Dot product with one of the 12 cube edge directions picked by the hash
+fn gradient(hash: usize, x: f32, y: f32, z: f32) -> f32 {
+	let h = hash & 15;
+	let u = if h < 8 { x } else { y };
+	let v = if h < 4 {
+		y
+	} else if h == 12 || h == 14 {
+		x
+	} else {
+		z
+	};
+
+	(if h & 1 == 0 { u } else { -u }) + (if h & 2 == 0 { v } else { -v })
+}
+
+fn fade(t: f32) -> f32 {
+	t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(t: f32, a: f32, b: f32) -> f32 {
+	a + t * (b - a)
+}
+
+fn mix(a: Vector3, b: Vector3, t: f32) -> Vector3 {
+	a + (b - a) * t
+}
+
+// Improved gradient noise [Perlin 2002], in [-1, 1] and zero at integer coordinates
+pub fn perlin(p: Vector3) -> f32 {
+	let (fx, fy, fz) = (p.x.floor(), p.y.floor(), p.z.floor());
+	let (xi, yi, zi) = (fx as i32, fy as i32, fz as i32);
+	let (x, y, z) = (p.x - fx, p.y - fy, p.z - fz);
+	let (u, v, w) = (fade(x), fade(y), fade(z));
+
+	let a = hash(xi) + (yi & 255) as usize;
+	let aa = hash(a as i32) + (zi & 255) as usize;
+	let ab = hash(a as i32 + 1) + (zi & 255) as usize;
+	let b = hash(xi + 1) + (yi & 255) as usize;
+	let ba = hash(b as i32) + (zi & 255) as usize;
+	let bb = hash(b as i32 + 1) + (zi & 255) as usize;
+
+	let corner =
+		|i: usize, dx: f32, dy: f32, dz: f32| gradient(hash(i as i32), x - dx, y - dy, z - dz);
+
+	lerp(
+		w,
+		lerp(
+			v,
+			lerp(u, corner(aa, 0.0, 0.0, 0.0), corner(ba, 1.0, 0.0, 0.0)),
+			lerp(u, corner(ab, 0.0, 1.0, 0.0), corner(bb, 1.0, 1.0, 0.0)),
+		),
+		lerp(
+			v,
+			lerp(
+				u,
+				corner(aa + 1, 0.0, 0.0, 1.0),
+				corner(ba + 1, 1.0, 0.0, 1.0),
+			),
+			lerp(
+				u,
+				corner(ab + 1, 0.0, 1.0, 1.0),
+				corner(bb + 1, 1.0, 1.0, 1.0),
+			),
+		),
+	)
+}
+
+// Fractional Brownian motion: octaves of noise with doubling frequency and halving amplitude,
+// normalized to [-1, 1]
+pub fn fbm(p: Vector3, octaves: u32) -> f32 {
+	let mut sum = 0.0;
+	let mut amplitude = 1.0;
+	let mut frequency = 1.0;
+	let mut total = 0.0;
+
+	for _ in 0..octaves.max(1) {
+		sum += amplitude * perlin(p * frequency);
+		total += amplitude;
+		amplitude *= 0.5;
+		frequency *= 2.0;
+	}
+
+	sum / total
+}
+
+// Like fBm, but summing the absolute value of each octave, which creates sharp creases. In [0, 1].
+pub fn turbulence(p: Vector3, octaves: u32) -> f32 {
+	let mut sum = 0.0;
+	let mut amplitude = 1.0;
+	let mut frequency = 1.0;
+	let mut total = 0.0;
+
+	for _ in 0..octaves.max(1) {
+		sum += amplitude * perlin(p * frequency).abs();
+		total += amplitude;
+		amplitude *= 0.5;
+		frequency *= 2.0;
+	}
+
+	sum / total
+}
+
+// Smooth fBm noise, blending between two colors
+#[derive(Copy, Clone, Debug)]
+pub struct Noise {
+	pub low: Vector3,
+	pub high: Vector3,
+	pub scale: f32,
+	pub octaves: u32,
+	pub mapping: Mapping,
+}
+
+impl Noise {
+	pub fn new(low: Vector3, high: Vector3, scale: f32) -> Noise {
+		Noise {
+			low,
+			high,
+			scale,
+			octaves: 1,
+			mapping: Mapping::Position,
+		}
+	}
+
+	pub fn with_octaves(mut self, octaves: u32) -> Noise {
+		self.octaves = octaves;
+		self
+	}
+
+	pub fn with_mapping(mut self, mapping: Mapping) -> Noise {
+		self.mapping = mapping;
+		self
+	}
+}
+
+impl Texture for Noise {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let n = fbm(self.mapping.point(hit, self.scale), self.octaves);
+		mix(self.low, self.high, (0.5 + 0.5 * n).clamp(0.0, 1.0))
+	}
+}
+
+// Turbulent noise, blending between two colors
+#[derive(Copy, Clone, Debug)]
+pub struct Turbulence {
+	pub low: Vector3,
+	pub high: Vector3,
+	pub scale: f32,
+	pub octaves: u32,
+	pub mapping: Mapping,
+}
+
+impl Turbulence {
+	pub fn new(low: Vector3, high: Vector3, scale: f32) -> Turbulence {
+		Turbulence {
+			low,
+			high,
+			scale,
+			octaves: 6,
+			mapping: Mapping::Position,
+		}
+	}
+
+	pub fn with_octaves(mut self, octaves: u32) -> Turbulence {
+		self.octaves = octaves;
+		self
+	}
+
+	pub fn with_mapping(mut self, mapping: Mapping) -> Turbulence {
+		self.mapping = mapping;
+		self
+	}
+}
+
+impl Texture for Turbulence {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let t = turbulence(self.mapping.point(hit, self.scale), self.octaves);
+		mix(self.low, self.high, t.clamp(0.0, 1.0))
+	}
+}
+
+// Veins along the x axis, made by distorting a sine wave with turbulence
+#[derive(Copy, Clone, Debug)]
+pub struct Marble {
+	pub base: Vector3,
+	pub vein: Vector3,
+	pub scale: f32,
+	pub distortion: f32,
+	pub octaves: u32,
+	pub mapping: Mapping,
+}
+
+impl Marble {
+	pub fn new(base: Vector3, vein: Vector3, scale: f32) -> Marble {
+		Marble {
+			base,
+			vein,
+			scale,
+			distortion: 5.0,
+			octaves: 6,
+			mapping: Mapping::Position,
+		}
+	}
+
+	pub fn with_distortion(mut self, distortion: f32) -> Marble {
+		self.distortion = distortion;
+		self
+	}
+
+	pub fn with_mapping(mut self, mapping: Mapping) -> Marble {
+		self.mapping = mapping;
+		self
+	}
+}
+
+impl Texture for Marble {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let p = self.mapping.point(hit, self.scale);
+		let phase = p.x + self.distortion * turbulence(p, self.octaves);
+
+		// Narrow veins at the troughs of the wave
+		let t = 1.0 - (0.5 + 0.5 * phase.sin()).sqrt();
+		mix(self.base, self.vein, t * t)
+	}
+}
+
+// Concentric growth rings around the y axis, with noise perturbing their radius
+#[derive(Copy, Clone, Debug)]
+pub struct Wood {
+	pub light: Vector3,
+	pub dark: Vector3,
+	pub scale: f32,
+	pub rings: f32,
+	pub distortion: f32,
+	pub mapping: Mapping,
+}
+
+impl Wood {
+	pub fn new(light: Vector3, dark: Vector3, scale: f32, rings: f32) -> Wood {
+		Wood {
+			light,
+			dark,
+			scale,
+			rings,
+			distortion: 0.1,
+			mapping: Mapping::Position,
+		}
+	}
+
+	pub fn with_distortion(mut self, distortion: f32) -> Wood {
+		self.distortion = distortion;
+		self
+	}
+
+	pub fn with_mapping(mut self, mapping: Mapping) -> Wood {
+		self.mapping = mapping;
+		self
+	}
+}
+
+impl Texture for Wood {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let p = self.mapping.point(hit, self.scale);
+		let radius = (p.x * p.x + p.z * p.z).sqrt() + self.distortion * fbm(p, 3);
+		let ring = (radius * self.rings).fract();
+
+		// Light early wood fading into a darker late wood band at the end of each ring
+		mix(self.light, self.dark, ring * ring)
+	}
+}
+
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum GradientShape {
+	// Along the line from the origin to the end point
+	Linear,
+	// Outwards from the origin, reaching the end color at the distance of the end point
+	Radial,
+}
+
+// Blend between two colors in a given region of space, clamped outside of it
+#[derive(Copy, Clone, Debug)]
+pub struct Gradient {
+	pub start: Vector3,
+	pub end: Vector3,
+	pub origin: Vector3,
+	pub target: Vector3,
+	pub shape: GradientShape,
+	pub mapping: Mapping,
+}
+
+impl Gradient {
+	pub fn linear(start: Vector3, end: Vector3, origin: Vector3, target: Vector3) -> Gradient {
+		Gradient {
+			start,
+			end,
+			origin,
+			target,
+			shape: GradientShape::Linear,
+			mapping: Mapping::Position,
+		}
+	}
+
+	pub fn radial(start: Vector3, end: Vector3, origin: Vector3, target: Vector3) -> Gradient {
+		Gradient {
+			shape: GradientShape::Radial,
+			..Gradient::linear(start, end, origin, target)
+		}
+	}
+
+	pub fn with_mapping(mut self, mapping: Mapping) -> Gradient {
+		self.mapping = mapping;
+		self
+	}
+}
+
+impl Texture for Gradient {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let p = self.mapping.point(hit, 1.0) - self.origin;
+		let axis = self.target - self.origin;
+		let length_squared = axis.dot(axis);
+		if length_squared == 0.0 {
+			return self.end;
+		}
+
+		let t = match self.shape {
+			GradientShape::Linear => p.dot(axis) / length_squared,
+			GradientShape::Radial => (p.dot(p) / length_squared).sqrt(),
+		};
+
+		mix(self.start, self.end, t.clamp(0.0, 1.0))
+	}
+}
@@ -4,10 +4,25 @@ use bvh::Vector3;
 pub struct Ray {
 	pub origin: Vector3,
 	pub direction: Vector3,
+	// Ray cone, to estimate the footprint of the ray on surfaces when filtering textures:
+	// width at the origin, and angle it grows by with distance
+	pub cone_width: f32,
+	pub cone_spread: f32,
 }
 
 impl Ray {
 	pub fn new(origin: Vector3, direction: Vector3) -> Ray {
-		Ray { origin, direction }
+		Ray {
+			origin,
+			direction,
+			cone_width: 0.0,
+			cone_spread: 0.0,
+		}
+	}
+
+	pub fn with_cone(mut self, width: f32, spread: f32) -> Ray {
+		self.cone_width = width;
+		self.cone_spread = spread;
+		self
 	}
 }
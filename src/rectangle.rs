@@ -1,11 +1,15 @@
+use bvh::aabb::AABB;
 use bvh::Vector3;
+use light::LightSample;
 use material::Material;
-use plane::Plane;
 use ray::Ray;
+use sampling::area_to_solid_angle;
+use Hit;
 use PrimitiveType;
 use Traceable;
+use Vector2;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Rectangle {
 	pub position: Vector3,
 	pub normal: Vector3,
@@ -37,30 +41,49 @@ impl Rectangle {
 			material,
 		}
 	}
+
+	// Density of sampling `p` as seen from `origin`. Rays only hit the front of the rectangle, so points
+	// behind it can't see it either.
+	fn pdf_at(&self, origin: Vector3, p: Vector3) -> f32 {
+		if self.normal.dot(origin - p) <= 0.0 {
+			return 0.0;
+		}
+
+		area_to_solid_angle(1.0 / (self.width * self.height), origin, p, self.normal)
+	}
 }
 
 impl Traceable for Rectangle {
 	// Ray-Rectangle Intersection
 	fn intersect(&self, r: &Ray, result: &mut ::Hit) -> bool {
-		let rectangle_plane = Plane::new(self.position, self.normal, self.material);
-		let hit = rectangle_plane.intersect(r, result);
+		// Intersect the supporting plane
+		let plane_normal = -self.normal;
+		let denom = plane_normal.dot(r.direction);
 
-		if hit {
-			let p = r.origin + r.direction * result.t;
+		if denom > 1e-6 {
+			let t = plane_normal.dot(self.position - r.origin) / denom;
+			let p = r.origin + r.direction * t;
 			let v = p - self.position;
 
 			let half_width = self.width * 0.5;
 			let half_height = self.height * 0.5;
 
 			// Project in 2D plane and clamp inside the rectangle
-			if v.dot(self.left).abs() <= half_width && v.dot(self.up).abs() <= half_height {
+			let x = v.dot(self.left);
+			let y = v.dot(self.up);
+
+			if x.abs() <= half_width && y.abs() <= half_height {
+				result.t = t;
 				result.p = p;
+				result.uv = Vector2::new(x / self.width + 0.5, y / self.height + 0.5);
+				result.dpdu = self.left * self.width;
+				result.dpdv = self.up * self.height;
+				result.ng = self.normal;
 				result.n = if self.normal.dot(r.direction) < 0.0 {
 					self.normal
 				} else {
 					-self.normal
 				};
-				result.material = self.material;
 				return true;
 			}
 		}
@@ -71,4 +94,43 @@ impl Traceable for Rectangle {
 	fn get_primitive_type(&self) -> PrimitiveType {
 		PrimitiveType::Rectangle
 	}
+
+	fn get_material(&self) -> &Material {
+		&self.material
+	}
+
+	fn sample(&self, origin: Vector3, u1: f32, u2: f32) -> Option<LightSample> {
+		// Uniformly sample the area, and convert to solid angle
+		let p = self.position
+			+ self.left * ((u1 - 0.5) * self.width)
+			+ self.up * ((u2 - 0.5) * self.height);
+		let pdf = self.pdf_at(origin, p);
+
+		if pdf == 0.0 {
+			return None;
+		}
+
+		Some(LightSample::new(p, self.normal, Vector2::new(u1, u2), pdf))
+	}
+
+	fn pdf(&self, origin: Vector3, hit: &Hit) -> f32 {
+		self.pdf_at(origin, hit.p)
+	}
+
+	fn bounds(&self) -> Option<AABB> {
+		let left = self.left * (0.5 * self.width);
+		let up = self.up * (0.5 * self.height);
+
+		Some(
+			AABB::empty()
+				.grow(&(self.position - left - up))
+				.grow(&(self.position + left - up))
+				.grow(&(self.position - left + up))
+				.grow(&(self.position + left + up)),
+		)
+	}
+
+	fn area(&self) -> f32 {
+		self.width * self.height
+	}
 }
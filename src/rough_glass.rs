@@ -0,0 +1,173 @@
+use bsdf::{fresnel_schlick, BSDFSample, Glass, BSDF};
+use bvh::Vector3;
+use hit::Hit;
+use microfacet::{reflect, GGX};
+
+// Rough dielectric (ie: frosted glass), with microfacet reflection and transmission [Walter et al. 2007].
+// Like Glass, radiance isn't rescaled by the relative IOR when crossing the interface.
+#[derive(Copy, Clone, Debug)]
+pub struct RoughGlass {
+	pub distribution: GGX,
+	pub ior: f32,
+}
+
+impl RoughGlass {
+	pub fn new(ior: f32, roughness: f32) -> RoughGlass {
+		RoughGlass {
+			distribution: GGX::from_roughness(roughness, 0.0),
+			ior,
+		}
+	}
+
+	// Relative IOR across the interface (transmitted side over viewer side), and whether the ray enters the medium
+	fn relative_ior(&self, hit: &Hit) -> (f32, bool) {
+		let into = hit.n.dot(hit.ng) > 0.0;
+		(if into { self.ior } else { 1.0 / self.ior }, into)
+	}
+
+	// Fresnel reflectance and cosine of the transmitted direction, for a microfacet normal
+	fn fresnel(&self, cos_o: f32, eta: f32, into: bool) -> (f32, f32) {
+		let sin2_t = (1.0 - cos_o * cos_o) / (eta * eta);
+
+		if sin2_t >= 1.0 {
+			// Total internal reflection
+			(1.0, 0.0)
+		} else {
+			let cos_t = (1.0 - sin2_t).sqrt();
+			(
+				fresnel_schlick(if into { cos_o } else { cos_t }, self.ior),
+				cos_t,
+			)
+		}
+	}
+
+	// Generalized half vector, facing the viewer's side
+	fn half_vector(wo: Vector3, wi: Vector3, eta: f32) -> Vector3 {
+		let m = if wi.z > 0.0 {
+			(wo + wi).normalize()
+		} else {
+			(wo + wi * eta).normalize()
+		};
+
+		if m.z < 0.0 {
+			-m
+		} else {
+			m
+		}
+	}
+}
+
+impl BSDF for RoughGlass {
+	fn sample(&self, hit: &Hit, wo: Vector3, uc: f32, u1: f32, u2: f32) -> Option<BSDFSample> {
+		let distribution = self.distribution.scaled(hit.material.roughness);
+
+		if distribution.is_smooth() {
+			return Glass::new(self.ior).sample(hit, wo, uc, u1, u2);
+		}
+
+		if wo.z <= 0.0 {
+			return None;
+		}
+
+		let (eta, into) = self.relative_ior(hit);
+		let m = distribution.sample_visible(wo, u1, u2);
+		let cos_o = wo.dot(m);
+		if cos_o <= 0.0 {
+			return None;
+		}
+
+		// Pick reflection or transmission proportionally to the Fresnel term
+		let (fresnel, cos_t) = self.fresnel(cos_o, eta, into);
+		let wi = if uc < fresnel {
+			let wi = reflect(wo, m);
+			if wi.z <= 0.0 {
+				return None;
+			}
+			wi
+		} else {
+			let wi = m * (cos_o / eta - cos_t) - wo / eta;
+			if wi.z >= 0.0 {
+				return None;
+			}
+			wi.normalize()
+		};
+
+		let pdf = self.pdf(hit, wo, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		let weight = hit.material.albedo * (distribution.g(wo, wi) / distribution.g1(wo));
+
+		Some(BSDFSample::new(wi, weight, pdf, false))
+	}
+
+	fn eval(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> Vector3 {
+		let distribution = self.distribution.scaled(hit.material.roughness);
+
+		if wo.z <= 0.0 || wi.z == 0.0 || distribution.is_smooth() {
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+
+		let (eta, into) = self.relative_ior(hit);
+		let m = RoughGlass::half_vector(wo, wi, eta);
+		let cos_o = wo.dot(m);
+		let cos_i = wi.dot(m);
+		if cos_o <= 0.0 {
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+
+		let (fresnel, _) = self.fresnel(cos_o, eta, into);
+		let d = distribution.d(m);
+		let g = distribution.g(wo, wi);
+
+		let f = if wi.z > 0.0 {
+			fresnel * d * g / (4.0 * wo.z * wi.z)
+		} else {
+			if cos_i >= 0.0 {
+				return Vector3::new(0.0, 0.0, 0.0);
+			}
+
+			let denom = cos_o + eta * cos_i;
+			eta * eta * (1.0 - fresnel) * d * g * (cos_o * cos_i).abs()
+				/ ((wo.z * wi.z).abs() * denom * denom)
+		};
+
+		hit.material.albedo * f
+	}
+
+	fn pdf(&self, hit: &Hit, wo: Vector3, wi: Vector3) -> f32 {
+		let distribution = self.distribution.scaled(hit.material.roughness);
+
+		if wo.z <= 0.0 || wi.z == 0.0 || distribution.is_smooth() {
+			return 0.0;
+		}
+
+		let (eta, into) = self.relative_ior(hit);
+		let m = RoughGlass::half_vector(wo, wi, eta);
+		let cos_o = wo.dot(m);
+		let cos_i = wi.dot(m);
+		if cos_o <= 0.0 {
+			return 0.0;
+		}
+
+		let (fresnel, _) = self.fresnel(cos_o, eta, into);
+		let pdf_m = distribution.pdf_visible(wo, m);
+
+		if wi.z > 0.0 {
+			fresnel * pdf_m / (4.0 * cos_o)
+		} else {
+			if cos_i >= 0.0 {
+				return 0.0;
+			}
+
+			// Jacobian of the refraction mapping
+			let denom = cos_o + eta * cos_i;
+			(1.0 - fresnel) * pdf_m * eta * eta * cos_i.abs() / (denom * denom)
+		}
+	}
+
+	fn is_specular(&self, hit: &Hit) -> bool {
+		self.distribution.scaled(hit.material.roughness).is_smooth()
+	}
+}
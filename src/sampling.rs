@@ -0,0 +1,301 @@
+use bvh::Vector3;
+use std::f32::consts::PI;
+use Vector2;
+
+// Build two vectors that form an orthonormal basis with the (normalized) input vector
+pub fn orthonormal_basis(normal: Vector3) -> (Vector3, Vector3) {
+	let w_up = if normal.x.abs() > 0.1 {
+		Vector3::new(0.0, 1.0, 0.0)
+	} else {
+		Vector3::new(1.0, 0.0, 0.0)
+	};
+
+	let tangent = normal.cross(w_up).normalize();
+	let bitangent = normal.cross(tangent).normalize();
+
+	(tangent, bitangent)
+}
+
+// Uniformly sample a direction inside a cone of half-angle acos(cos_theta_max) around `axis`
+pub fn sample_cone(axis: Vector3, cos_theta_max: f32, u1: f32, u2: f32) -> Vector3 {
+	let cos_theta = 1.0 - u1 * (1.0 - cos_theta_max);
+	let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+	let phi = 2.0 * PI * u2;
+	let (tangent, bitangent) = orthonormal_basis(axis);
+
+	tangent * phi.cos() * sin_theta + bitangent * phi.sin() * sin_theta + axis * cos_theta
+}
+
+// Sample a direction on the hemisphere around +z, with a density proportional to the cosine
+pub fn sample_cosine_hemisphere(u1: f32, u2: f32) -> Vector3 {
+	let r1 = 2.0 * PI * u1;
+	let r2s = u2.sqrt();
+
+	Vector3::new(r1.cos() * r2s, r1.sin() * r2s, (1.0 - u2).sqrt())
+}
+
+// Uniformly sample a direction on the unit sphere
+pub fn sample_sphere(u1: f32, u2: f32) -> Vector3 {
+	let z = 1.0 - 2.0 * u1;
+	let r = (1.0 - z * z).max(0.0).sqrt();
+	let phi = 2.0 * PI * u2;
+
+	Vector3::new(r * phi.cos(), r * phi.sin(), z)
+}
+
+// Convert a density with respect to surface area at `p` into one with respect to solid angle at `origin`
+pub fn area_to_solid_angle(pdf_area: f32, origin: Vector3, p: Vector3, n: Vector3) -> f32 {
+	let to_p = p - origin;
+	let dist_sqrd = to_p.dot(to_p);
+	let cos_theta = n.dot(to_p).abs() / dist_sqrd.sqrt();
+
+	if cos_theta < 1e-6 {
+		0.0
+	} else {
+		pdf_area * dist_sqrd / cos_theta
+	}
+}
+
+// Uniformly sample barycentric coordinates over a triangle
+pub fn sample_triangle(u1: f32, u2: f32) -> Vector3 {
+	let su = u1.sqrt();
+	let b1 = 1.0 - su;
+	let b2 = u2 * su;
+
+	Vector3::new(1.0 - b1 - b2, b1, b2)
+}
+
+// Multiple importance sampling weight for a sample drawn from `pdf_f`, against the strategy `pdf_g`
+pub fn power_heuristic(pdf_f: f32, pdf_g: f32) -> f32 {
+	let f = pdf_f * pdf_f;
+	let g = pdf_g * pdf_g;
+
+	if f + g > 0.0 {
+		f / (f + g)
+	} else {
+		0.0
+	}
+}
+
+// Orthonormal shading frame, with the normal as the local z axis
+#[derive(Copy, Clone, Debug)]
+pub struct Frame {
+	pub tangent: Vector3,
+	pub bitangent: Vector3,
+	pub normal: Vector3,
+}
+
+impl Frame {
+	pub fn new(normal: Vector3) -> Frame {
+		let (tangent, bitangent) = orthonormal_basis(normal);
+
+		Frame {
+			tangent,
+			bitangent,
+			normal,
+		}
+	}
+
+	// Frame with the tangent following `dpdu` projected onto the surface, so anisotropic BSDFs line up
+	// with the surface parameterization. Degenerate derivatives fall back to an arbitrary tangent.
+	pub fn from_tangent(normal: Vector3, dpdu: Vector3) -> Frame {
+		let tangent = dpdu - normal * normal.dot(dpdu);
+		let length = tangent.length();
+
+		if length > 1e-6 * dpdu.length() {
+			let tangent = tangent / length;
+
+			Frame {
+				tangent,
+				bitangent: normal.cross(tangent),
+				normal,
+			}
+		} else {
+			Frame::new(normal)
+		}
+	}
+
+	pub fn to_local(&self, v: Vector3) -> Vector3 {
+		Vector3::new(
+			v.dot(self.tangent),
+			v.dot(self.bitangent),
+			v.dot(self.normal),
+		)
+	}
+
+	pub fn to_world(&self, v: Vector3) -> Vector3 {
+		self.tangent * v.x + self.bitangent * v.y + self.normal * v.z
+	}
+}
+
+// Piecewise constant distribution over [0, 1], sampled by inverting its CDF
+#[derive(Clone, Debug)]
+pub struct Distribution1D {
+	pub function: Vec<f32>,
+	pub integral: f32,
+	cdf: Vec<f32>,
+}
+
+impl Distribution1D {
+	pub fn new(function: Vec<f32>) -> Distribution1D {
+		let n = function.len();
+		let mut cdf = vec![0.0; n + 1];
+		for i in 0..n {
+			cdf[i + 1] = cdf[i] + function[i].max(0.0) / n as f32;
+		}
+
+		// Fall back to a uniform distribution if the function is zero everywhere
+		let integral = cdf[n];
+		for (i, c) in cdf.iter_mut().enumerate().skip(1) {
+			*c = if integral > 0.0 {
+				*c / integral
+			} else {
+				i as f32 / n as f32
+			};
+		}
+
+		Distribution1D {
+			function,
+			integral,
+			cdf,
+		}
+	}
+
+	pub fn len(&self) -> usize {
+		self.function.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.function.is_empty()
+	}
+
+	// Returns the sampled position, its density, and the index of the segment it falls in
+	pub fn sample(&self, u: f32) -> (f32, f32, usize) {
+		let index = (self.cdf.partition_point(|&c| c <= u).max(1) - 1).min(self.len() - 1);
+
+		let width = self.cdf[index + 1] - self.cdf[index];
+		let offset = if width > 0.0 {
+			((u - self.cdf[index]) / width).clamp(0.0, 1.0)
+		} else {
+			0.0
+		};
+
+		(
+			((index as f32 + offset) / self.len() as f32).min(1.0 - f32::EPSILON),
+			self.pdf(index),
+			index,
+		)
+	}
+
+	// Density of the segment `index`
+	pub fn pdf(&self, index: usize) -> f32 {
+		if self.integral > 0.0 {
+			self.function[index].max(0.0) / self.integral
+		} else {
+			1.0
+		}
+	}
+
+	// Pick a segment with a probability proportional to its value, returning it with that probability
+	pub fn sample_discrete(&self, u: f32) -> (usize, f32) {
+		let (_, _, index) = self.sample(u);
+		(index, self.probability(index))
+	}
+
+	pub fn probability(&self, index: usize) -> f32 {
+		self.pdf(index) / self.len() as f32
+	}
+}
+
+// Piecewise constant distribution over [0, 1]², stored row by row. Rows (v) are picked from their
+// marginal distribution, then the column (u) from the distribution of the row.
+#[derive(Clone, Debug)]
+pub struct Distribution2D {
+	conditional: Vec<Distribution1D>,
+	marginal: Distribution1D,
+}
+
+impl Distribution2D {
+	pub fn new(function: &[f32], width: usize, height: usize) -> Distribution2D {
+		assert_eq!(function.len(), width * height, "distribution size mismatch");
+
+		let conditional: Vec<Distribution1D> = function
+			.chunks(width)
+			.map(|row| Distribution1D::new(row.to_vec()))
+			.collect();
+		let marginal = Distribution1D::new(conditional.iter().map(|row| row.integral).collect());
+
+		Distribution2D {
+			conditional,
+			marginal,
+		}
+	}
+
+	// Returns the sampled point and its density
+	pub fn sample(&self, u1: f32, u2: f32) -> (Vector2, f32) {
+		let (v, pdf_v, row) = self.marginal.sample(u2);
+		let (u, pdf_u, _) = self.conditional[row].sample(u1);
+
+		(Vector2::new(u, v), pdf_u * pdf_v)
+	}
+
+	pub fn pdf(&self, uv: Vector2) -> f32 {
+		let height = self.marginal.len();
+		let row = ((uv.y * height as f32) as usize).min(height - 1);
+		let width = self.conditional[row].len();
+		let column = ((uv.x * width as f32) as usize).min(width - 1);
+
+		self.conditional[row].pdf(column) * self.marginal.pdf(row)
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	fn assert_orthonormal(frame: &Frame) {
+		for &(a, b) in [
+			(frame.tangent, frame.bitangent),
+			(frame.bitangent, frame.normal),
+			(frame.normal, frame.tangent),
+		]
+		.iter()
+		{
+			assert!(a.dot(b).abs() < 1e-5);
+			assert!((a.length() - 1.0).abs() < 1e-5);
+		}
+
+		// Right handed, like the frame built from the normal alone
+		assert!((frame.tangent.cross(frame.bitangent) - frame.normal).length() < 1e-5);
+	}
+
+	#[test]
+	fn frame_follows_tangent() {
+		let normal = Vector3::new(1.0, 2.0, 2.0) / 3.0;
+		let dpdu = Vector3::new(3.0, 0.0, -1.0);
+		let frame = Frame::from_tangent(normal, dpdu);
+		assert_orthonormal(&frame);
+
+		// The tangent is dpdu projected onto the surface
+		let projected = (dpdu - normal * normal.dot(dpdu)).normalize();
+		assert!((frame.tangent - projected).length() < 1e-5);
+		assert!((frame.to_world(frame.to_local(dpdu)) - dpdu).length() < 1e-5);
+	}
+
+	#[test]
+	fn frame_falls_back_for_degenerate_tangent() {
+		let normal = Vector3::new(0.0, 1.0, 0.0);
+
+		for &dpdu in [
+			Vector3::new(0.0, 0.0, 0.0),
+			Vector3::new(0.0, 5.0, 0.0),
+			Vector3::new(f32::NAN, 0.0, 0.0),
+		]
+		.iter()
+		{
+			let frame = Frame::from_tangent(normal, dpdu);
+			assert_orthonormal(&frame);
+			assert_eq!(frame.tangent, Frame::new(normal).tangent);
+		}
+	}
+}
@@ -1,87 +1,640 @@
-use bsdf::BSDF;
+use bump::adapt_shading_normal;
 use bvh::aabb::{Bounded, AABB};
-use bvh::bounding_hierarchy::BoundingHierarchy;
-use bvh::bvh::BVH;
-use bvh::ray::Ray as NewRay;
-use bvh::Point3;
+use bvh::Vector3;
 use hit::Hit;
+use light::Light;
+use light_sampler::{LightInfo, LightSampler, PowerLightSampler};
+use luminance;
 use ray::Ray;
-use std::f32::INFINITY;
+use std;
+use std::f32::consts::PI;
+use std::sync::OnceLock;
 use triangle::Triangle;
+use wide_bvh::BVH8;
+use PrimitiveType;
 use Traceable;
 
+// Hits closer than this to the ray origin are ignored, so rays leaving a surface don't hit it again
+const T_MIN: f32 = 1e-6;
+
 // #[derive(Default)]
 pub struct Scene {
 	pub objects: Vec<Box<dyn Traceable>>,
 	pub triangles: Vec<Triangle>,
-	//
-	bvh: BVH,
-	bvh_built: bool,
+	// Indices of the emissive objects, sampled explicitly by the integrator
+	pub lights: Vec<usize>,
+	// Lights without geometry, sampled alongside the emissive objects
+	pub light_sources: Vec<Box<dyn Light>>,
+	// Indices of the emissive triangles
+	pub triangle_lights: Vec<usize>,
+	// Built on first use from all the objects and triangles, and reset when geometry is added
+	acceleration: OnceLock<Acceleration>,
+	// Built on first use from all the lights, and reset when lights are added
+	light_sampler: OnceLock<Box<dyn LightSampler>>,
+	build_light_sampler: fn(&[LightInfo]) -> Box<dyn LightSampler>,
+}
+
+// Bounded object or triangle, as referenced by the BVH
+#[derive(Copy, Clone, Debug)]
+struct Primitive {
+	// Index of the object, or usize::MAX for triangles
+	object: usize,
+	// Index of the triangle, or usize::MAX for objects
+	triangle: usize,
+}
+
+// One BVH over every bounded object and triangle. Unbounded objects (ie: planes) are kept aside, and
+// tested against every ray.
+struct Acceleration {
+	bvh: BVH8,
+	primitives: Vec<Primitive>,
+	unbounded: Vec<usize>,
+}
+
+impl Acceleration {
+	fn build(objects: &[Box<dyn Traceable>], triangles: &[Triangle]) -> Acceleration {
+		let mut primitives = vec![];
+		let mut bounds = vec![];
+		let mut unbounded = vec![];
+
+		for (index, object) in objects.iter().enumerate() {
+			match object.bounds() {
+				Some(object_bounds) => {
+					primitives.push(Primitive {
+						object: index,
+						triangle: usize::MAX,
+					});
+					bounds.push(object_bounds);
+				}
+				None => unbounded.push(index),
+			}
+		}
+
+		for (index, triangle) in triangles.iter().enumerate() {
+			primitives.push(Primitive {
+				object: usize::MAX,
+				triangle: index,
+			});
+			bounds.push(triangle.aabb());
+		}
+
+		Acceleration {
+			bvh: BVH8::build(&bounds),
+			primitives,
+			unbounded,
+		}
+	}
+}
+
+fn build_light_sampler<S: LightSampler + 'static>(lights: &[LightInfo]) -> Box<dyn LightSampler> {
+	Box::new(S::build(lights))
 }
 
 impl Scene {
+	// Emissive objects are registered as lights, except instances which cannot be sampled
 	pub fn add(&mut self, obj: Box<dyn Traceable>) {
+		if obj.get_material().emission != Vector3::new(0.0, 0.0, 0.0)
+			&& obj.get_primitive_type() != PrimitiveType::Instance
+		{
+			self.lights.push(self.objects.len());
+			self.light_sampler = OnceLock::new();
+		}
+
 		self.objects.push(obj);
+		self.acceleration = OnceLock::new();
+	}
+
+	pub fn add_light(&mut self, light: Box<dyn Light>) {
+		self.light_sources.push(light);
+		self.light_sampler = OnceLock::new();
+	}
+
+	// Choose how lights are picked for next-event estimation (by power by default)
+	pub fn set_light_sampler<S: LightSampler + 'static>(&mut self) {
+		self.build_light_sampler = build_light_sampler::<S>;
+		self.light_sampler = OnceLock::new();
+	}
+
+	// Number of lights picked from by light sampling: emissive objects first, then light sources, then
+	// emissive triangles
+	pub fn num_lights(&self) -> usize {
+		self.lights.len() + self.light_sources.len() + self.triangle_lights.len()
+	}
+
+	pub fn is_light(&self, object: usize) -> bool {
+		self.lights.binary_search(&object).is_ok()
+	}
+
+	// Power and extent of every light, in the order used by light sampling
+	pub fn light_infos(&self) -> Vec<LightInfo> {
+		// Diffuse emitters radiate from the side rays can hit, which is both sides for triangles
+		let emitter = |shape: &dyn Traceable| {
+			let sides = if shape.get_primitive_type() == PrimitiveType::Triangle {
+				2.0
+			} else {
+				1.0
+			};
+			let power = sides * PI * shape.area() * luminance(shape.get_material().emission);
+			LightInfo::new(power, shape.bounds())
+		};
+
+		let scene_bounds = self
+			.objects
+			.iter()
+			.filter_map(|object| object.bounds())
+			.chain(self.triangles.iter().map(|triangle| triangle.aabb()))
+			.fold(AABB::empty(), |bounds, other| bounds.join(&other));
+		let scene_radius = if scene_bounds.is_empty() {
+			1.0
+		} else {
+			0.5 * scene_bounds.size().length()
+		};
+
+		let mut infos: Vec<LightInfo> = self
+			.lights
+			.iter()
+			.map(|&object| emitter(self.objects[object].as_ref()))
+			.chain(
+				self.light_sources
+					.iter()
+					.map(|light| LightInfo::new(light.power(scene_radius), light.bounds())),
+			)
+			.chain(
+				self.triangle_lights
+					.iter()
+					.map(|&triangle| emitter(&self.triangles[triangle])),
+			)
+			.collect();
+
+		// Lights without a usable power estimate (ie: textured emission) get the average power, so they
+		// can still be picked
+		let valid = infos
+			.iter()
+			.filter(|info| info.power > 0.0 && info.power.is_finite());
+		let (count, total) = valid.fold((0, 0.0), |(count, total), info| {
+			(count + 1, total + info.power)
+		});
+		let average = if count > 0 { total / count as f32 } else { 1.0 };
+
+		for info in infos.iter_mut() {
+			if !(info.power > 0.0 && info.power.is_finite()) {
+				info.power = average;
+			}
+		}
+
+		infos
+	}
+
+	fn light_sampler(&self) -> &dyn LightSampler {
+		self.light_sampler
+			.get_or_init(|| (self.build_light_sampler)(&self.light_infos()))
+			.as_ref()
+	}
+
+	// Pick a light for the shading point `p`, returning its index and the probability of picking it
+	pub fn sample_light(&self, p: Vector3, u: f32) -> Option<(usize, f32)> {
+		self.light_sampler().sample(p, u)
+	}
+
+	// Probability of `sample_light` picking the light `index` for the shading point `p`
+	pub fn light_probability(&self, p: Vector3, index: usize) -> f32 {
+		self.light_sampler().probability(p, index)
+	}
+
+	// Probability density of light sampling picking the hit point, with respect to solid angle at `origin`
+	pub fn light_pdf(&self, origin: Vector3, hit: &Hit) -> f32 {
+		if let Ok(index) = self.lights.binary_search(&hit.object) {
+			return self.objects[hit.object].pdf(origin, hit)
+				* self.light_probability(origin, index);
+		}
+
+		if hit.object != usize::MAX {
+			return 0.0;
+		}
+
+		if let Ok(index) = self.triangle_lights.binary_search(&hit.primitive) {
+			let index = self.lights.len() + self.light_sources.len() + index;
+			return self.triangles[hit.primitive].pdf(origin, hit)
+				* self.light_probability(origin, index);
+		}
+
+		0.0
 	}
 
 	pub fn add_triangle(&mut self, triangle: Triangle) {
+		if triangle.material.emission != Vector3::new(0.0, 0.0, 0.0) {
+			self.triangle_lights.push(self.triangles.len());
+			self.light_sampler = OnceLock::new();
+		}
+
 		self.triangles.push(triangle);
+		self.acceleration = OnceLock::new();
 	}
 
 	pub fn init() -> Scene {
 		Scene {
 			objects: vec![],
 			triangles: vec![],
-			bvh: BVH { nodes: vec![] },
-			bvh_built: false,
+			lights: vec![],
+			light_sources: vec![],
+			triangle_lights: vec![],
+			acceleration: OnceLock::new(),
+			light_sampler: OnceLock::new(),
+			build_light_sampler: build_light_sampler::<PowerLightSampler>,
+		}
+	}
+
+	fn acceleration(&self) -> &Acceleration {
+		self.acceleration
+			.get_or_init(|| Acceleration::build(&self.objects, &self.triangles))
+	}
+
+	// Whether anything blocks the ray between `t_min` and `t_max`, as for shadow rays or line of sight
+	// checks. Returns at the first blocker found, rather than looking for the closest one.
+	pub fn occluded(&self, ray: Ray, t_min: f32, t_max: f32) -> bool {
+		let acceleration = self.acceleration();
+		let mut scratch = Hit::init();
+
+		for &object in acceleration.unbounded.iter() {
+			if self.objects[object].occludes(&ray, t_min, t_max, &mut scratch) {
+				return true;
+			}
 		}
+
+		acceleration.bvh.occluded(&ray, t_max, |index| {
+			let primitive = acceleration.primitives[index];
+			if primitive.object != usize::MAX {
+				self.objects[primitive.object].occludes(&ray, t_min, t_max, &mut scratch)
+			} else {
+				self.triangles[primitive.triangle].occludes(&ray, t_min, t_max, &mut scratch)
+			}
+		})
 	}
 
-	pub fn intersect(&self, ray: Ray) -> Option<Hit> {
+	pub fn intersect<'a>(&'a self, ray: Ray) -> Option<Hit> {
 		let mut final_hit = Hit::init();
+		let blank = Hit::init();
+		let mut current_hit = Hit::init();
+		let mut closest: Option<&'a dyn Traceable> = None;
+
+		let acceleration = self.acceleration();
 
-		// Intersect parametric scene objects
-		for s in 0..self.objects.len() {
-			let mut current_hit = Hit::init();
-			let hit = self.objects[s].intersect(&ray, &mut current_hit);
+		// Keep the hit if it is the closest so far, and return its distance
+		let mut test = |shape: &'a dyn Traceable, object: usize, triangle: usize, t_max: f32| {
+			// Start from a blank hit, so fields a shape doesn't write aren't left over from another one.
+			// Shapes made of several primitives (ie: instances) fill in the one they hit, and skip those
+			// beyond the closest hit so far.
+			current_hit = Hit {
+				t: t_max,
+				primitive: triangle,
+				..blank.clone()
+			};
+			let hit = shape.intersect(&ray, &mut current_hit);
 
-			// todo: hit min&max
-			if hit && current_hit.t < final_hit.t && current_hit.t > 1e-6 {
-				final_hit = current_hit;
+			if hit && current_hit.t > T_MIN && current_hit.t < t_max {
+				std::mem::swap(&mut final_hit, &mut current_hit);
+				final_hit.object = object;
+				closest = Some(shape);
+				Some(final_hit.t)
+			} else {
+				None
+			}
+		};
+
+		// Unbounded objects are always tested
+		let mut t_max = f32::INFINITY;
+		for &object in acceleration.unbounded.iter() {
+			if let Some(t) = test(self.objects[object].as_ref(), object, usize::MAX, t_max) {
+				t_max = t;
 			}
 		}
 
-		if self.bvh_built {
-			let bvh_ray = NewRay::new(
-				Point3::new(ray.origin.x, ray.origin.y, ray.origin.z),
-				Point3::new(ray.direction.x, ray.direction.y, ray.direction.z),
-			);
-			let hits = self.bvh.traverse(&bvh_ray, &self.triangles);
+		// Front to back through the BVH, skipping nodes beyond the closest hit so far
+		acceleration.bvh.intersect(&ray, t_max, |index, t_max| {
+			let primitive = acceleration.primitives[index];
+			if primitive.object != usize::MAX {
+				let object = primitive.object;
+				test(self.objects[object].as_ref(), object, usize::MAX, t_max)
+			} else {
+				let triangle = primitive.triangle;
+				test(&self.triangles[triangle], usize::MAX, triangle, t_max)
+			}
+		});
+
+		// Only fetch the material of the closest hit, with its textures evaluated
+		closest.map(|object| {
+			final_hit.footprint = ray.cone_width + ray.cone_spread * final_hit.t;
+			let material = object.material_at(&final_hit);
+			final_hit.material = material.at(&final_hit);
+
+			if material.normal_texture.is_some() || material.bump_texture.is_some() {
+				final_hit.n = material.normal_at(&final_hit);
+			}
+			final_hit.n = adapt_shading_normal(final_hit.n, final_hit.ng, -ray.direction);
+			final_hit
+		})
+	}
+
+	// Build the BVH ahead of rendering, rather than on first use. Also picks up triangles pushed directly
+	// into the list, rather than through `add_triangle`.
+	pub fn build_bvh(&mut self) {
+		let acceleration = Acceleration::build(&self.objects, &self.triangles);
+		self.acceleration = OnceLock::from(acceleration);
+
+		self.triangle_lights = (0..self.triangles.len())
+			.filter(|&index| self.triangles[index].material.emission != Vector3::new(0.0, 0.0, 0.0))
+			.collect();
+		self.light_sampler = OnceLock::new();
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bsdf::Diffuse;
+	use instance::Instance;
+	use material::Material;
+	use mesh::Mesh;
+	use plane::Plane;
+	use rand::rngs::StdRng;
+	use rand::{Rng, SeedableRng};
+	use rectangle::Rectangle;
+	use sampling::orthonormal_basis;
+	use sphere::Sphere;
+	use std::sync::Arc;
+	use Transform;
+
+	fn random_point(rng: &mut StdRng, scale: f32) -> Vector3 {
+		(Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0) * scale
+	}
 
-			// Triangles vs BVH
-			if !hits.is_empty() {
-				let mut current_hit = Hit::init();
+	fn random_triangle(rng: &mut StdRng, center: Vector3) -> Triangle {
+		Triangle::new(
+			center + random_point(rng, 0.5),
+			center + random_point(rng, 0.5),
+			center + random_point(rng, 0.5),
+			Material::white(),
+		)
+	}
+
+	// Every kind of shape: bounded objects, an unbounded plane, loose triangles and instances
+	fn random_scene(rng: &mut StdRng) -> Scene {
+		let mut scene = Scene::init();
+
+		scene.add(Box::new(Plane::new(
+			Vector3::new(0.0, -6.0, 0.0),
+			Vector3::new(0.0, 1.0, 0.0),
+			Material::white(),
+		)));
+
+		for _ in 0..30 {
+			let center = random_point(rng, 5.0);
+			scene.add(Box::new(Sphere::new(
+				rng.gen_range(0.1..0.5),
+				center,
+				Material::white(),
+			)));
+
+			let normal = random_point(rng, 1.0).normalize();
+			let (left, up) = orthonormal_basis(normal);
+			scene.add(Box::new(Rectangle::new(
+				random_point(rng, 5.0),
+				normal,
+				left,
+				up,
+				rng.gen_range(0.2..1.0),
+				rng.gen_range(0.2..1.0),
+				Material::white(),
+			)));
+		}
+
+		for _ in 0..200 {
+			let center = random_point(rng, 5.0);
+			scene.add_triangle(random_triangle(rng, center));
+		}
 
-				// Of all the hits, return the closest hit
-				for hit in hits {
-					let is_hit = hit.intersect(&ray, &mut current_hit);
+		let mesh = Arc::new(Mesh::new(
+			(0..50)
+				.map(|_| {
+					let center = random_point(rng, 1.0);
+					random_triangle(rng, center)
+				})
+				.collect(),
+		));
+		for _ in 0..5 {
+			let transform = Transform::from_scale_rotation_translation(
+				Vector3::splat(rng.gen_range(0.5..1.5)),
+				glam::Quat::from_axis_angle(random_point(rng, 1.0).normalize(), rng.gen()),
+				random_point(rng, 4.0),
+			);
+			scene.add(Box::new(Instance::new(mesh.clone(), transform)));
+		}
+
+		scene
+	}
 
-					if is_hit && current_hit.t < final_hit.t && current_hit.t > 1e-6 {
-						final_hit = current_hit;
-					}
+	fn random_rays(rng: &mut StdRng) -> Vec<Ray> {
+		(0..2000)
+			.map(|i| {
+				let origin = random_point(rng, 7.0);
+				let mut direction = random_point(rng, 1.0).normalize();
+				// Some rays along the axes
+				if i % 4 == 0 {
+					direction[i % 3] = 0.0;
+					direction = direction.normalize();
 				}
+				Ray::new(origin, direction)
+			})
+			.collect()
+	}
+
+	// Triangles are hit from both sides, so they radiate twice the power of a rectangle of the same area
+	#[test]
+	fn triangle_lights_emit_on_both_sides() {
+		let emissive = Material::new(Vector3::splat(2.0), Vector3::splat(0.0), Diffuse);
+		let mut scene = Scene::init();
+		scene.add(Box::new(Rectangle::new(
+			Vector3::new(0.0, 0.0, 0.0),
+			Vector3::new(0.0, 0.0, 1.0),
+			Vector3::new(1.0, 0.0, 0.0),
+			Vector3::new(0.0, 1.0, 0.0),
+			1.0,
+			1.0,
+			emissive.clone(),
+		)));
+		let triangle = Triangle::new(
+			Vector3::new(0.0, 0.0, 1.0),
+			Vector3::new(2.0, 0.0, 1.0),
+			Vector3::new(0.0, 1.0, 1.0),
+			emissive,
+		);
+		scene.add(Box::new(triangle.clone()));
+		scene.add_triangle(triangle);
+
+		let infos = scene.light_infos();
+		let rectangle = PI * 2.0;
+		assert!((infos[0].power - rectangle).abs() < 1e-5 * rectangle);
+		assert!((infos[1].power - 2.0 * rectangle).abs() < 1e-5 * rectangle);
+		assert!((infos[2].power - 2.0 * rectangle).abs() < 1e-5 * rectangle);
+	}
+
+	// Closest hit over every shape, each intersected with a fresh hit
+	fn brute_force_intersect(scene: &Scene, ray: &Ray) -> Option<Hit> {
+		let objects = scene
+			.objects
+			.iter()
+			.enumerate()
+			.map(|(index, object)| (object.as_ref(), index, usize::MAX));
+		let triangles = scene
+			.triangles
+			.iter()
+			.enumerate()
+			.map(|(index, triangle)| (triangle as &dyn Traceable, usize::MAX, index));
+
+		let mut closest: Option<Hit> = None;
+		for (shape, object, primitive) in objects.chain(triangles) {
+			let mut hit = Hit::init();
+			hit.primitive = primitive;
+			let t_max = closest.as_ref().map_or(f32::INFINITY, |hit| hit.t);
+			if shape.intersect(ray, &mut hit) && hit.t > T_MIN && hit.t < t_max {
+				hit.object = object;
+				closest = Some(hit);
 			}
 		}
+		closest
+	}
 
-		if final_hit.t != INFINITY {
-			Some(final_hit)
-		} else {
-			None
+	#[test]
+	fn intersect_matches_brute_force() {
+		let mut rng = StdRng::seed_from_u64(3);
+		let scene = random_scene(&mut rng);
+
+		let mut hits = 0;
+		for ray in random_rays(&mut rng).iter() {
+			let hit = scene.intersect(*ray);
+			let expected = brute_force_intersect(&scene, ray);
+			assert_eq!(
+				hit.is_some(),
+				expected.is_some(),
+				"{:?} towards {:?}",
+				ray.origin,
+				ray.direction
+			);
+
+			if let (Some(hit), Some(expected)) = (hit, expected) {
+				assert_eq!(
+					(hit.object, hit.primitive),
+					(expected.object, expected.primitive),
+					"{:?} towards {:?}",
+					ray.origin,
+					ray.direction
+				);
+				assert!((hit.t - expected.t).abs() < 1e-5 * expected.t);
+				assert_eq!(hit.p, expected.p);
+				assert_eq!(hit.ng, expected.ng);
+				assert_eq!(hit.b, expected.b);
+				assert_eq!(hit.uv, expected.uv);
+				assert_eq!((hit.dpdu, hit.dpdv), (expected.dpdu, expected.dpdv));
+				hits += 1;
+			}
 		}
+		assert!(hits > 500, "{} hits", hits);
 	}
 
-	pub fn build_bvh(&mut self) {
-		self.bvh = BVH::build(&mut self.triangles);
-		self.bvh_built = true; //boo
+	// Shape without bounds, so the scene tests it against every ray in the order it was added
+	struct Unbounded<T>(T);
+
+	impl<T: Traceable> Traceable for Unbounded<T> {
+		fn intersect(&self, ray: &Ray, result: &mut Hit) -> bool {
+			self.0.intersect(ray, result)
+		}
+
+		fn get_primitive_type(&self) -> PrimitiveType {
+			self.0.get_primitive_type()
+		}
+
+		fn get_material(&self) -> &Material {
+			self.0.get_material()
+		}
+	}
+
+	// Spheres don't write barycentric coordinates, which must not be left over from a triangle tested before
+	#[test]
+	fn intersect_starts_from_blank_hits() {
+		let mut scene = Scene::init();
+		scene.add(Box::new(Unbounded(Triangle::new(
+			Vector3::new(-1.0, -1.0, -5.0),
+			Vector3::new(1.0, -1.0, -5.0),
+			Vector3::new(0.0, 1.0, -5.0),
+			Material::white(),
+		))));
+		for &z in [-3.0, -2.0].iter() {
+			scene.add(Box::new(Unbounded(Sphere::new(
+				0.5,
+				Vector3::new(0.0, 0.0, z),
+				Material::white(),
+			))));
+		}
+
+		let hit = scene
+			.intersect(Ray::new(Vector3::splat(0.0), Vector3::new(0.0, 0.0, -1.0)))
+			.unwrap();
+		assert_eq!(hit.object, 2);
+		assert!((hit.t - 1.5).abs() < 1e-5);
+		assert_eq!(hit.b, Vector3::splat(0.0));
+	}
+
+	#[test]
+	fn occluded_matches_brute_force() {
+		let mut rng = StdRng::seed_from_u64(1);
+		let scene = random_scene(&mut rng);
+		let mut scratch = Hit::init();
+
+		let mut blocked = 0;
+		for ray in random_rays(&mut rng).iter() {
+			for &(t_min, t_max) in
+				[(1e-6, 2.0), (1e-6, 8.0), (3.0, 6.0), (1e-6, f32::INFINITY)].iter()
+			{
+				let expected = scene
+					.objects
+					.iter()
+					.map(|object| object.as_ref())
+					.chain(
+						scene
+							.triangles
+							.iter()
+							.map(|triangle| triangle as &dyn Traceable),
+					)
+					.any(|shape| shape.occludes(ray, t_min, t_max, &mut scratch));
+
+				assert_eq!(
+					scene.occluded(*ray, t_min, t_max),
+					expected,
+					"occlusion of {:?} towards {:?} between {} and {}",
+					ray.origin,
+					ray.direction,
+					t_min,
+					t_max
+				);
+				blocked += expected as usize;
+			}
+		}
+		assert!(blocked > 1000 && blocked < 7000, "{} blocked", blocked);
+	}
+
+	#[test]
+	fn occluded_agrees_with_intersect() {
+		let mut rng = StdRng::seed_from_u64(2);
+		let scene = random_scene(&mut rng);
+
+		for ray in random_rays(&mut rng).iter() {
+			match scene.intersect(*ray) {
+				Some(hit) => {
+					assert!(!scene.occluded(*ray, 1e-6, hit.t * 0.999));
+					assert!(scene.occluded(*ray, 1e-6, hit.t * 1.001));
+				}
+				None => assert!(!scene.occluded(*ray, 1e-6, f32::INFINITY)),
+			}
+		}
 	}
 }
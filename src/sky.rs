@@ -0,0 +1,388 @@
+use bvh::Vector3;
+use environment::{equirectangular_direction, equirectangular_pdf, equirectangular_uv};
+use light::{Incident, Light};
+use luminance;
+use sampling::{sample_cone, Distribution2D};
+use spectrum::xyz_to_rgb;
+use std::f32::consts::PI;
+use Vector2;
+
+// Angular radius of the solar disc, in radians
+const SUN_ANGULAR_RADIUS: f32 = 0.00465;
+
+// Luminance of the sun outside the atmosphere, in kcd/m²
+const SUN_LUMINANCE: f32 = 2.0e6;
+
+// Resolution of the latitude/longitude table used to importance sample the sky
+const TABLE_WIDTH: usize = 128;
+const TABLE_HEIGHT: usize = 64;
+
+// Perez sky luminance distribution, relative to the zenith
+#[derive(Copy, Clone, Debug)]
+struct Perez {
+	a: f32,
+	b: f32,
+	c: f32,
+	d: f32,
+	e: f32,
+}
+
+impl Perez {
+	fn evaluate(&self, cos_theta: f32, gamma: f32) -> f32 {
+		let cos_gamma = gamma.cos();
+
+		(1.0 + self.a * (self.b / cos_theta).exp())
+			* (1.0 + self.c * (self.d * gamma).exp() + self.e * cos_gamma * cos_gamma)
+	}
+}
+
+// Daylight sky [Preetham et al. 1999] with a matching solar disc, both driven by the sun direction and
+// the atmospheric turbidity. Radiance is in kcd/m², scaled by `intensity`. Nothing is emitted below the
+// horizon, which is expected to be covered by the ground.
+#[derive(Clone, Debug)]
+pub struct Sky {
+	pub sun_direction: Vector3,
+	pub turbidity: f32,
+	pub intensity: f32,
+	//
+	perez: [Perez; 3],
+	// Zenith luminance and chromaticity, and the Perez functions evaluated at the zenith
+	zenith: [f32; 3],
+	zenith_perez: [f32; 3],
+	sun_radiance: Vector3,
+	cos_sun_radius: f32,
+	distribution: Distribution2D,
+	// Probability of sampling the solar disc rather than the sky
+	sun_probability: f32,
+	// Luminance of the sky and sun integrated over all directions, before scaling by the intensity
+	integrated_luminance: f32,
+}
+
+impl Sky {
+	// `sun_direction` points towards the sun. Turbidity ranges from 2 (clear) to 10 (hazy).
+	pub fn new(sun_direction: Vector3, turbidity: f32, intensity: f32) -> Sky {
+		let sun_direction = sun_direction.normalize();
+		let t = turbidity.clamp(1.7, 10.0);
+
+		// The model is only valid for a sun above the horizon
+		let theta_s = sun_direction.y.clamp(0.0, 1.0).acos();
+		let (t2, ts, ts2, ts3) = (
+			t * t,
+			theta_s,
+			theta_s * theta_s,
+			theta_s * theta_s * theta_s,
+		);
+
+		let perez = [
+			Perez {
+				a: 0.1787 * t - 1.4630,
+				b: -0.3554 * t + 0.4275,
+				c: -0.0227 * t + 5.3251,
+				d: 0.1206 * t - 2.5771,
+				e: -0.0670 * t + 0.3703,
+			},
+			Perez {
+				a: -0.0193 * t - 0.2592,
+				b: -0.0665 * t + 0.0008,
+				c: -0.0004 * t + 0.2125,
+				d: -0.0641 * t - 0.8989,
+				e: -0.0033 * t + 0.0452,
+			},
+			Perez {
+				a: -0.0167 * t - 0.2608,
+				b: -0.0950 * t + 0.0092,
+				c: -0.0079 * t + 0.2102,
+				d: -0.0441 * t - 1.6537,
+				e: -0.0109 * t + 0.0529,
+			},
+		];
+
+		let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * ts);
+		let zenith = [
+			((4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192).max(0.0),
+			t2 * (0.00166 * ts3 - 0.00375 * ts2 + 0.00209 * ts)
+				+ t * (-0.02903 * ts3 + 0.06377 * ts2 - 0.03202 * ts + 0.00394)
+				+ (0.11693 * ts3 - 0.21196 * ts2 + 0.06052 * ts + 0.25886),
+			t2 * (0.00275 * ts3 - 0.00610 * ts2 + 0.00317 * ts)
+				+ t * (-0.04214 * ts3 + 0.08970 * ts2 - 0.04153 * ts + 0.00516)
+				+ (0.15346 * ts3 - 0.26756 * ts2 + 0.06670 * ts + 0.26688),
+		];
+
+		let zenith_perez = [
+			perez[0].evaluate(1.0, theta_s),
+			perez[1].evaluate(1.0, theta_s),
+			perez[2].evaluate(1.0, theta_s),
+		];
+
+		let sun_radiance = if sun_direction.y > 0.0 {
+			sun_transmittance(theta_s, t) * SUN_LUMINANCE
+		} else {
+			Vector3::new(0.0, 0.0, 0.0)
+		};
+
+		let mut sky = Sky {
+			sun_direction,
+			turbidity: t,
+			intensity,
+			perez,
+			zenith,
+			zenith_perez,
+			sun_radiance,
+			cos_sun_radius: SUN_ANGULAR_RADIUS.cos(),
+			distribution: Distribution2D::new(&[1.0], 1, 1),
+			sun_probability: 0.0,
+			integrated_luminance: 0.0,
+		};
+
+		// Tabulate the sky luminance for sampling, accounting for the solid angle of each row
+		let mut weights = Vec::with_capacity(TABLE_WIDTH * TABLE_HEIGHT);
+		let mut sky_power = 0.0;
+		for y in 0..TABLE_HEIGHT {
+			for x in 0..TABLE_WIDTH {
+				let uv = Vector2::new(
+					(x as f32 + 0.5) / TABLE_WIDTH as f32,
+					(y as f32 + 0.5) / TABLE_HEIGHT as f32,
+				);
+				let sin_theta = (uv.y * PI).sin();
+				let weight = luminance(sky.sky_radiance(equirectangular_direction(uv))) * sin_theta;
+
+				weights.push(weight);
+				sky_power += weight;
+			}
+		}
+		sky.distribution = Distribution2D::new(&weights, TABLE_WIDTH, TABLE_HEIGHT);
+
+		// Pick the sun and the sky following the irradiance they deliver
+		let sky_power = sky_power * 2.0 * PI * PI / (TABLE_WIDTH * TABLE_HEIGHT) as f32;
+		let sun_power = luminance(sky.sun_radiance) * 2.0 * PI * (1.0 - sky.cos_sun_radius);
+		if sun_power + sky_power > 0.0 {
+			sky.sun_probability = sun_power / (sun_power + sky_power);
+		}
+		sky.integrated_luminance = sun_power + sky_power;
+
+		sky
+	}
+
+	// Radiance of the sky alone, without the sun and before scaling by the intensity
+	fn sky_radiance(&self, direction: Vector3) -> Vector3 {
+		if direction.y <= 0.0 {
+			return Vector3::new(0.0, 0.0, 0.0);
+		}
+
+		// Keep the Perez function finite at the horizon
+		let cos_theta = direction.y.max(0.01);
+		let gamma = direction.dot(self.sun_direction).clamp(-1.0, 1.0).acos();
+
+		let mut xy_y = [0.0; 3];
+		for (i, value) in xy_y.iter_mut().enumerate() {
+			*value =
+				self.zenith[i] * self.perez[i].evaluate(cos_theta, gamma) / self.zenith_perez[i];
+		}
+
+		xy_y_to_rgb(xy_y[1], xy_y[2], xy_y[0])
+	}
+
+	fn is_in_sun(&self, direction: Vector3) -> bool {
+		direction.dot(self.sun_direction) >= self.cos_sun_radius
+	}
+
+	fn sun_pdf(&self) -> f32 {
+		1.0 / (2.0 * PI * (1.0 - self.cos_sun_radius))
+	}
+}
+
+// xyY color to linear sRGB, through CIE XYZ
+fn xy_y_to_rgb(x: f32, y: f32, luminance: f32) -> Vector3 {
+	if y <= 0.0 {
+		return Vector3::new(0.0, 0.0, 0.0);
+	}
+
+	let xyz = Vector3::new(x / y * luminance, luminance, (1.0 - x - y) / y * luminance);
+	xyz_to_rgb(xyz).max(Vector3::new(0.0, 0.0, 0.0))
+}
+
+// Fraction of sunlight passing through the atmosphere, from Rayleigh and aerosol scattering along the
+// optical path. Evaluated at representative wavelengths for the red, green and blue channels.
+fn sun_transmittance(theta_s: f32, turbidity: f32) -> Vector3 {
+	let relative_mass = 1.0 / (theta_s.cos() + 0.15 * (93.885 - theta_s.to_degrees()).powf(-1.253));
+	let beta = 0.04608 * turbidity - 0.04586;
+
+	let transmittance = |wavelength: f32| {
+		let rayleigh = -0.008735 * wavelength.powf(-4.08) * relative_mass;
+		let aerosol = -beta * wavelength.powf(-1.3) * relative_mass;
+		(rayleigh + aerosol).exp()
+	};
+
+	// Wavelengths in micrometers
+	Vector3::new(
+		transmittance(0.68),
+		transmittance(0.55),
+		transmittance(0.44),
+	)
+}
+
+impl Light for Sky {
+	fn sample(&self, origin: Vector3, u1: f32, u2: f32) -> Option<Incident> {
+		let wi = if u1 < self.sun_probability {
+			let u1 = u1 / self.sun_probability;
+			sample_cone(self.sun_direction, self.cos_sun_radius, u1, u2)
+		} else {
+			let u1 = (u1 - self.sun_probability) / (1.0 - self.sun_probability);
+			let (uv, _) = self.distribution.sample(u1.min(1.0 - f32::EPSILON), u2);
+			equirectangular_direction(uv)
+		};
+
+		let pdf = self.pdf(origin, wi);
+		if pdf <= 0.0 {
+			return None;
+		}
+
+		Some(Incident::new(wi, f32::INFINITY, self.emission(wi), pdf))
+	}
+
+	fn is_infinite(&self) -> bool {
+		true
+	}
+
+	fn emission(&self, direction: Vector3) -> Vector3 {
+		let mut radiance = self.sky_radiance(direction);
+		if self.is_in_sun(direction) {
+			radiance += self.sun_radiance;
+		}
+
+		radiance * self.intensity
+	}
+
+	fn pdf(&self, _origin: Vector3, direction: Vector3) -> f32 {
+		let uv = equirectangular_uv(direction);
+		let mut pdf =
+			(1.0 - self.sun_probability) * equirectangular_pdf(uv, self.distribution.pdf(uv));
+
+		if self.is_in_sun(direction) {
+			pdf += self.sun_probability * self.sun_pdf();
+		}
+
+		pdf
+	}
+
+	// Power falling onto a disk covering the scene
+	fn power(&self, scene_radius: f32) -> f32 {
+		PI * scene_radius * scene_radius * self.intensity * self.integrated_luminance
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use rand::rngs::StdRng;
+	use rand::{Rng, SeedableRng};
+
+	// Histogram of sampled directions over latitude/longitude bins, each covering 4x4 cells of the table
+	const BINS_X: usize = TABLE_WIDTH / 4;
+	const BINS_Y: usize = TABLE_HEIGHT / 4;
+	const NUM_SAMPLES: usize = 200_000;
+
+	fn bin(uv: Vector2) -> usize {
+		let x = ((uv.x * BINS_X as f32) as usize).min(BINS_X - 1);
+		let y = ((uv.y * BINS_Y as f32) as usize).min(BINS_Y - 1);
+		y * BINS_X + x
+	}
+
+	// Skies with a high and a low sun, in the middle of a bin so that the whole solar disc falls in it
+	fn skies() -> Vec<Sky> {
+		[(10, 2, 3.0), (21, 6, 6.0)]
+			.iter()
+			.map(|&(x, y, turbidity)| {
+				let uv = Vector2::new(
+					(x as f32 + 0.5) / BINS_X as f32,
+					(y as f32 + 0.5) / BINS_Y as f32,
+				);
+				Sky::new(equirectangular_direction(uv), turbidity, 1.0)
+			})
+			.collect()
+	}
+
+	#[test]
+	fn sampled_pdf_matches_pdf() {
+		let origin = Vector3::new(0.0, 0.0, 0.0);
+		let mut rng = StdRng::seed_from_u64(1);
+
+		for sky in skies() {
+			assert!(sky.sun_probability > 0.0 && sky.sun_probability < 1.0);
+			let mut in_sun = 0;
+
+			for _ in 0..10000 {
+				let incident = match sky.sample(origin, rng.gen(), rng.gen()) {
+					Some(incident) => incident,
+					None => continue,
+				};
+				let pdf = sky.pdf(origin, incident.wi);
+				assert!(
+					(incident.pdf - pdf).abs() <= 1e-3 * pdf,
+					"sampled {:?} with pdf {}, evaluated {}",
+					incident.wi,
+					incident.pdf,
+					pdf
+				);
+				assert_eq!(incident.radiance, sky.emission(incident.wi));
+				in_sun += sky.is_in_sun(incident.wi) as usize;
+			}
+
+			let fraction = in_sun as f32 / 10000.0;
+			assert!(
+				(fraction - sky.sun_probability).abs() < 0.02,
+				"{} in the sun",
+				fraction
+			);
+		}
+	}
+
+	#[test]
+	fn sampled_directions_follow_pdf() {
+		let origin = Vector3::new(0.0, 0.0, 0.0);
+		let mut rng = StdRng::seed_from_u64(2);
+
+		for sky in skies() {
+			let mut histogram = vec![0; BINS_X * BINS_Y];
+			for _ in 0..NUM_SAMPLES {
+				if let Some(incident) = sky.sample(origin, rng.gen(), rng.gen()) {
+					histogram[bin(equirectangular_uv(incident.wi))] += 1;
+				}
+			}
+
+			// The density of the sky is constant over each cell of the table in latitude/longitude
+			// coordinates, so one evaluation per cell integrates it exactly. The sun is too small to reach
+			// the middle of a cell, and is added as a whole.
+			let mut expected = vec![0.0; BINS_X * BINS_Y];
+			for y in 0..TABLE_HEIGHT {
+				for x in 0..TABLE_WIDTH {
+					let uv = Vector2::new(
+						(x as f32 + 0.5) / TABLE_WIDTH as f32,
+						(y as f32 + 0.5) / TABLE_HEIGHT as f32,
+					);
+					let direction = equirectangular_direction(uv);
+					assert!(!sky.is_in_sun(direction));
+
+					let pdf_uv = sky.pdf(origin, direction) * 2.0 * PI * PI * (uv.y * PI).sin();
+					expected[bin(uv)] += pdf_uv / (TABLE_WIDTH * TABLE_HEIGHT) as f32;
+				}
+			}
+			expected[bin(equirectangular_uv(sky.sun_direction))] += sky.sun_probability;
+
+			let total: f32 = expected.iter().sum();
+			assert!((total - 1.0).abs() < 1e-3, "total probability {}", total);
+
+			for (i, (&observed, &expected)) in histogram.iter().zip(expected.iter()).enumerate() {
+				let expected = expected * NUM_SAMPLES as f32;
+				let tolerance = 5.0 * expected.sqrt() + 0.01 * expected + 10.0;
+				assert!(
+					(observed as f32 - expected).abs() < tolerance,
+					"bin {} has {} samples instead of {}",
+					i,
+					observed,
+					expected
+				);
+			}
+		}
+	}
+}
@@ -0,0 +1,96 @@
+use bvh::Vector3;
+
+// Lumens per watt of radiant power at 555nm, where the eye is most sensitive
+pub const MAX_LUMINOUS_EFFICACY: f32 = 683.0;
+
+// Visible range of wavelengths, in nanometers
+pub const MIN_WAVELENGTH: f32 = 360.0;
+pub const MAX_WAVELENGTH: f32 = 830.0;
+
+// Piecewise gaussian, with different widths on each side of the mean
+fn gaussian(x: f32, mean: f32, sigma_low: f32, sigma_high: f32) -> f32 {
+	let t = (x - mean) / if x < mean { sigma_low } else { sigma_high };
+	(-0.5 * t * t).exp()
+}
+
+// CIE 1931 2° color matching functions, from their multi-lobe fit [Wyman et al. 2013].
+// The wavelength is in nanometers.
+pub fn cie_xyz(wavelength: f32) -> Vector3 {
+	let x = 1.056 * gaussian(wavelength, 599.8, 37.9, 31.0)
+		+ 0.362 * gaussian(wavelength, 442.0, 16.0, 26.7)
+		- 0.065 * gaussian(wavelength, 501.1, 20.4, 26.2);
+	let y = 0.821 * gaussian(wavelength, 568.8, 46.9, 40.5)
+		+ 0.286 * gaussian(wavelength, 530.9, 16.3, 31.1);
+	let z = 1.217 * gaussian(wavelength, 437.0, 11.8, 36.0)
+		+ 0.681 * gaussian(wavelength, 459.0, 26.0, 13.8);
+
+	Vector3::new(x, y, z)
+}
+
+// CIE XYZ to linear sRGB (Rec. 709 primaries, D65 white point)
+pub fn xyz_to_rgb(xyz: Vector3) -> Vector3 {
+	Vector3::new(
+		3.2406 * xyz.x - 1.5372 * xyz.y - 0.4986 * xyz.z,
+		-0.9689 * xyz.x + 1.8758 * xyz.y + 0.0415 * xyz.z,
+		0.0557 * xyz.x - 0.2040 * xyz.y + 1.0570 * xyz.z,
+	)
+}
+
+// Spectral radiance of a blackbody from Planck's law, in W/(m²·sr·nm). The wavelength is in nanometers
+// and the temperature in kelvin.
+pub fn planck(wavelength: f32, temperature: f32) -> f32 {
+	const H: f64 = 6.62607015e-34;
+	const C: f64 = 299792458.0;
+	const K: f64 = 1.380649e-23;
+
+	if temperature <= 0.0 {
+		return 0.0;
+	}
+
+	let lambda = wavelength as f64 * 1e-9;
+	let radiance = 2.0 * H * C * C
+		/ (lambda.powi(5) * ((H * C / (lambda * K * temperature as f64)).exp() - 1.0));
+
+	// Per meter to per nanometer
+	(radiance * 1e-9) as f32
+}
+
+// Integrate the color matching functions against a spectrum, in 1nm steps over the visible range
+fn integrate_xyz<F: Fn(f32) -> f32>(spectrum: F) -> Vector3 {
+	let mut xyz = Vector3::new(0.0, 0.0, 0.0);
+	let mut wavelength = MIN_WAVELENGTH;
+
+	while wavelength <= MAX_WAVELENGTH {
+		xyz += cie_xyz(wavelength) * spectrum(wavelength);
+		wavelength += 1.0;
+	}
+
+	xyz
+}
+
+// Linear sRGB color of a blackbody at the given temperature, normalized to a luminance of 1. Negative
+// components, for colors outside of the sRGB gamut, are clamped.
+pub fn blackbody(temperature: f32) -> Vector3 {
+	let xyz = integrate_xyz(|wavelength| planck(wavelength, temperature));
+	if xyz.y <= 0.0 {
+		return Vector3::new(0.0, 0.0, 0.0);
+	}
+
+	let rgb = xyz_to_rgb(xyz / xyz.y);
+	Vector3::new(rgb.x.max(0.0), rgb.y.max(0.0), rgb.z.max(0.0))
+}
+
+// Lumens per watt of total radiant power emitted by a blackbody at the given temperature
+pub fn blackbody_efficacy(temperature: f32) -> f32 {
+	const STEFAN_BOLTZMANN: f32 = 5.670374e-8;
+
+	if temperature <= 0.0 {
+		return 0.0;
+	}
+
+	// Total radiance over all wavelengths, from the Stefan-Boltzmann law
+	let total = STEFAN_BOLTZMANN * temperature.powi(4) / std::f32::consts::PI;
+	let visible = integrate_xyz(|wavelength| planck(wavelength, temperature)).y;
+
+	MAX_LUMINOUS_EFFICACY * visible / total
+}
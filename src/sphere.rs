@@ -1,12 +1,17 @@
+use bvh::aabb::AABB;
 use bvh::Vector3;
 use hit::Hit;
+use light::LightSample;
 use material::Material;
 use ray::Ray;
+use sampling::{area_to_solid_angle, orthonormal_basis, sample_cone, sample_sphere};
 use std;
+use std::f32::consts::PI;
 use PrimitiveType;
 use Traceable;
+use Vector2;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Sphere {
 	pub radius: f32,
 	pub position: Vector3,
@@ -24,6 +29,34 @@ impl Sphere {
 	}
 }
 
+// Longitude/latitude mapping of a direction from the center, with v going up
+fn spherical_uv(n: Vector3) -> Vector2 {
+	Vector2::new(
+		0.5 + n.z.atan2(n.x) / (2.0 * PI),
+		0.5 + n.y.clamp(-1.0, 1.0).asin() / PI,
+	)
+}
+
+// Partial derivatives of the position with respect to the spherical mapping
+fn spherical_tangents(n: Vector3, radius: f32) -> (Vector3, Vector3) {
+	let cos_latitude = (n.x * n.x + n.z * n.z).sqrt();
+
+	if cos_latitude < 1e-6 {
+		// At the poles, any tangent frame will do
+		let (tangent, bitangent) = orthonormal_basis(n);
+		return (tangent * (2.0 * PI * radius), bitangent * (PI * radius));
+	}
+
+	let dpdu = Vector3::new(-n.z, 0.0, n.x) * (2.0 * PI * radius);
+	let dpdv = Vector3::new(
+		-n.y * n.x / cos_latitude,
+		cos_latitude,
+		-n.y * n.z / cos_latitude,
+	) * (PI * radius);
+
+	(dpdu, dpdv)
+}
+
 impl Traceable for Sphere {
 	fn intersect(&self, ray: &Ray, result: &mut Hit) -> bool {
 		let op: Vector3 = self.position - ray.origin;
@@ -38,25 +71,31 @@ impl Traceable for Sphere {
 			if b - det > 0.01 {
 				result.t = b - det;
 				result.p = ray.origin + ray.direction * result.t;
-				result.n = (self.position - result.p).normalize();
-				result.n = if result.n.dot(ray.direction) < 0.0 {
-					result.n
+				result.ng = (result.p - self.position).normalize();
+				result.uv = spherical_uv(result.ng);
+				let (dpdu, dpdv) = spherical_tangents(result.ng, self.radius);
+				result.dpdu = dpdu;
+				result.dpdv = dpdv;
+				result.n = if result.ng.dot(ray.direction) < 0.0 {
+					result.ng
 				} else {
-					-result.n
+					-result.ng
 				};
-				result.material = self.material;
 
 				return true;
 			} else if b + det > 0.01 {
 				result.t = b + det;
 				result.p = ray.origin + ray.direction * result.t;
-				result.n = (self.position - result.p).normalize();
-				result.n = if result.n.dot(ray.direction) < 0.0 {
-					result.n
+				result.ng = (result.p - self.position).normalize();
+				result.uv = spherical_uv(result.ng);
+				let (dpdu, dpdv) = spherical_tangents(result.ng, self.radius);
+				result.dpdu = dpdu;
+				result.dpdv = dpdv;
+				result.n = if result.ng.dot(ray.direction) < 0.0 {
+					result.ng
 				} else {
-					-result.n
+					-result.ng
 				};
-				result.material = self.material;
 
 				return true;
 			}
@@ -68,4 +107,71 @@ impl Traceable for Sphere {
 	fn get_primitive_type(&self) -> PrimitiveType {
 		PrimitiveType::Sphere
 	}
+
+	fn get_material(&self) -> &Material {
+		&self.material
+	}
+
+	fn sample(&self, origin: Vector3, u1: f32, u2: f32) -> Option<LightSample> {
+		let to_center = self.position - origin;
+		let dist_sqrd = to_center.dot(to_center);
+		let radius_sqrd = self.radius * self.radius;
+
+		if dist_sqrd <= radius_sqrd {
+			// Inside the sphere: sample the whole surface uniformly, and convert to solid angle
+			let n = sample_sphere(u1, u2);
+			let p = self.position + n * self.radius;
+			let pdf = area_to_solid_angle(1.0 / (4.0 * PI * radius_sqrd), origin, p, n);
+
+			if pdf == 0.0 {
+				return None;
+			}
+
+			Some(LightSample::new(p, n, spherical_uv(n), pdf))
+		} else {
+			// Outside the sphere: uniformly sample the cone of directions it subtends
+			let dist = dist_sqrd.sqrt();
+			let cos_theta_max = (1.0 - radius_sqrd / dist_sqrd).max(0.0).sqrt();
+			let direction = sample_cone(to_center / dist, cos_theta_max, u1, u2);
+
+			// Closest intersection along the sampled direction
+			let b = to_center.dot(direction);
+			let det_sqrd = (b * b - dist_sqrd + radius_sqrd).max(0.0);
+			let p = origin + direction * (b - det_sqrd.sqrt());
+			let n = (p - self.position).normalize();
+
+			Some(LightSample::new(
+				p,
+				n,
+				spherical_uv(n),
+				1.0 / (2.0 * PI * (1.0 - cos_theta_max)),
+			))
+		}
+	}
+
+	fn pdf(&self, origin: Vector3, hit: &Hit) -> f32 {
+		let to_center = self.position - origin;
+		let dist_sqrd = to_center.dot(to_center);
+		let radius_sqrd = self.radius * self.radius;
+
+		if dist_sqrd <= radius_sqrd {
+			let n = (hit.p - self.position).normalize();
+			area_to_solid_angle(1.0 / (4.0 * PI * radius_sqrd), origin, hit.p, n)
+		} else {
+			let cos_theta_max = (1.0 - radius_sqrd / dist_sqrd).max(0.0).sqrt();
+			1.0 / (2.0 * PI * (1.0 - cos_theta_max))
+		}
+	}
+
+	fn bounds(&self) -> Option<AABB> {
+		let extent = Vector3::new(self.radius, self.radius, self.radius);
+		Some(AABB::with_bounds(
+			self.position - extent,
+			self.position + extent,
+		))
+	}
+
+	fn area(&self) -> f32 {
+		4.0 * PI * self.radius * self.radius
+	}
 }
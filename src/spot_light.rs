@@ -0,0 +1,125 @@
+use bvh::aabb::AABB;
+use bvh::Vector3;
+use light::{Incident, Light};
+use luminance;
+use std::f32::consts::PI;
+
+// Point light restricted to a cone, fading out smoothly between the falloff start and the cone angle
+#[derive(Copy, Clone, Debug)]
+pub struct SpotLight {
+	pub position: Vector3,
+	pub direction: Vector3,
+	// Radiant intensity along the axis, in W/sr
+	pub intensity: Vector3,
+	//
+	cos_cone_angle: f32,
+	cos_falloff_start: f32,
+}
+
+impl SpotLight {
+	// Angles are half-angles around the axis, in radians
+	pub fn new(
+		position: Vector3,
+		direction: Vector3,
+		intensity: Vector3,
+		cone_angle: f32,
+		falloff_start: f32,
+	) -> SpotLight {
+		SpotLight {
+			position,
+			direction: direction.normalize(),
+			intensity,
+			cos_cone_angle: cone_angle.cos(),
+			cos_falloff_start: falloff_start.min(cone_angle).cos(),
+		}
+	}
+
+	fn falloff(&self, cos_theta: f32) -> f32 {
+		if cos_theta >= self.cos_falloff_start {
+			return 1.0;
+		}
+
+		if cos_theta <= self.cos_cone_angle {
+			return 0.0;
+		}
+
+		let t = (cos_theta - self.cos_cone_angle) / (self.cos_falloff_start - self.cos_cone_angle);
+		t * t * (3.0 - 2.0 * t)
+	}
+}
+
+impl Light for SpotLight {
+	fn sample(&self, origin: Vector3, _u1: f32, _u2: f32) -> Option<Incident> {
+		let to_light = self.position - origin;
+		let distance_squared = to_light.dot(to_light);
+		if distance_squared == 0.0 {
+			return None;
+		}
+
+		let distance = distance_squared.sqrt();
+		let wi = to_light / distance;
+		let falloff = self.falloff(-wi.dot(self.direction));
+		if falloff == 0.0 {
+			return None;
+		}
+
+		Some(Incident::new(
+			wi,
+			distance,
+			self.intensity * (falloff / distance_squared),
+			1.0,
+		))
+	}
+
+	// Full intensity up to the falloff start, and about half of it on average through the falloff
+	fn power(&self, _scene_radius: f32) -> f32 {
+		let cos_average = 0.5 * (self.cos_falloff_start + self.cos_cone_angle);
+		2.0 * PI * (1.0 - cos_average) * luminance(self.intensity)
+	}
+
+	fn bounds(&self) -> Option<AABB> {
+		Some(AABB::with_bounds(self.position, self.position))
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+
+	// Points at distance 2 from a light pointing down, at the given angle from its axis
+	fn at_angle(light: &SpotLight, angle: f32) -> Option<Incident> {
+		let origin = light.position + Vector3::new(angle.sin(), -angle.cos(), 0.0) * 2.0;
+		light.sample(origin, 0.5, 0.5)
+	}
+
+	#[test]
+	fn cone_cutoff_and_falloff() {
+		let light = SpotLight::new(
+			Vector3::new(0.0, 3.0, 0.0),
+			Vector3::new(0.0, -2.0, 0.0),
+			Vector3::new(4.0, 4.0, 4.0),
+			0.6,
+			0.3,
+		);
+
+		// Full intensity inside the falloff start, falling off with the square of the distance
+		for &angle in [0.0, 0.2, 0.29].iter() {
+			let incident = at_angle(&light, angle).unwrap();
+			assert!((incident.radiance - Vector3::splat(1.0)).length() < 1e-5);
+			assert!((incident.distance - 2.0).abs() < 1e-5);
+		}
+
+		// Decreasing through the falloff
+		let mut previous = 1.0;
+		for i in 1..10 {
+			let incident = at_angle(&light, 0.3 + 0.03 * i as f32).unwrap();
+			assert!(incident.radiance.x < previous && incident.radiance.x > 0.0);
+			previous = incident.radiance.x;
+		}
+
+		// Nothing outside the cone, nor behind the light
+		assert!(at_angle(&light, 0.61).is_none());
+		assert!(at_angle(&light, 2.0).is_none());
+		assert!(at_angle(&light, PI).is_none());
+	}
+}
@@ -0,0 +1,89 @@
+use bvh::Vector3;
+use hit::Hit;
+
+// Spatially varying material parameter, evaluated for each hit
+pub trait Texture: Send + Sync {
+	fn evaluate(&self, hit: &Hit) -> Vector3;
+}
+
+#[derive(Copy, Clone, Debug)]
+pub struct Constant {
+	pub value: Vector3,
+}
+
+impl Constant {
+	pub fn new(value: Vector3) -> Constant {
+		Constant { value }
+	}
+}
+
+impl Texture for Constant {
+	fn evaluate(&self, _hit: &Hit) -> Vector3 {
+		self.value
+	}
+}
+
+// Alternating squares in UV space, with `frequency` squares per unit
+#[derive(Copy, Clone, Debug)]
+pub struct Checkerboard {
+	pub even: Vector3,
+	pub odd: Vector3,
+	pub frequency: f32,
+}
+
+impl Checkerboard {
+	pub fn new(even: Vector3, odd: Vector3, frequency: f32) -> Checkerboard {
+		Checkerboard {
+			even,
+			odd,
+			frequency,
+		}
+	}
+}
+
+impl Texture for Checkerboard {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let u = (hit.uv.x * self.frequency).floor() as i64;
+		let v = (hit.uv.y * self.frequency).floor() as i64;
+
+		if (u + v).rem_euclid(2) == 0 {
+			self.even
+		} else {
+			self.odd
+		}
+	}
+}
+
+// In-memory image, stored top row first, looked up with the nearest texel and repeated outside [0, 1]
+#[derive(Clone, Debug)]
+pub struct Bitmap {
+	pub width: usize,
+	pub height: usize,
+	pub pixels: Vec<Vector3>,
+}
+
+impl Bitmap {
+	pub fn new(width: usize, height: usize, pixels: Vec<Vector3>) -> Bitmap {
+		assert!(width > 0 && height > 0, "empty bitmap");
+		assert_eq!(pixels.len(), width * height, "bitmap size mismatch");
+
+		Bitmap {
+			width,
+			height,
+			pixels,
+		}
+	}
+}
+
+impl Texture for Bitmap {
+	fn evaluate(&self, hit: &Hit) -> Vector3 {
+		let u = hit.uv.x - hit.uv.x.floor();
+		let v = hit.uv.y - hit.uv.y.floor();
+
+		// v goes up, rows go down
+		let x = ((u * self.width as f32) as usize).min(self.width - 1);
+		let y = (((1.0 - v) * self.height as f32) as usize).min(self.height - 1);
+
+		self.pixels[y * self.width + x]
+	}
+}
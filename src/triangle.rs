@@ -1,15 +1,17 @@
-use bvh::{Point3, Vector3};
+use bvh::Vector3;
 use hit::Hit;
+use light::LightSample;
 use material::Material;
 use ray::Ray;
+use sampling::{area_to_solid_angle, orthonormal_basis, sample_triangle};
 use PrimitiveType;
 use Traceable;
+use Vector2;
 
 use bvh::aabb::{Bounded, AABB};
-use bvh::bounding_hierarchy::{BHShape, BoundingHierarchy};
-use bvh::bvh::BVH;
+use bvh::bounding_hierarchy::BHShape;
 
-#[derive(Copy, Clone)]
+#[derive(Clone)]
 pub struct Triangle {
 	pub p0: Vector3,
 	pub p1: Vector3,
@@ -18,6 +20,9 @@ pub struct Triangle {
 	pub n0: Vector3,
 	pub n1: Vector3,
 	pub n2: Vector3,
+	pub uv0: Vector2,
+	pub uv1: Vector2,
+	pub uv2: Vector2,
 	//
 	pub material: Material,
 	//
@@ -36,6 +41,9 @@ impl Triangle {
 			n0: (p2 - p0).normalize().cross((p1 - p0).normalize()),
 			n1: (p2 - p0).normalize().cross((p1 - p0).normalize()),
 			n2: (p2 - p0).normalize().cross((p1 - p0).normalize()),
+			uv0: Vector2::new(0.0, 0.0),
+			uv1: Vector2::new(1.0, 0.0),
+			uv2: Vector2::new(0.0, 1.0),
 			material,
 			aabb: AABB::empty().grow(&p0).grow(&p1).grow(&p2),
 			node_index: 0,
@@ -59,47 +67,93 @@ impl Triangle {
 			n1,
 			n2,
 			normal: (p2 - p0).normalize().cross((p1 - p0).normalize()),
+			uv0: Vector2::new(0.0, 0.0),
+			uv1: Vector2::new(1.0, 0.0),
+			uv2: Vector2::new(0.0, 1.0),
 			material,
 			aabb: AABB::empty().grow(&p0).grow(&p1).grow(&p2),
 			node_index: 0,
 		}
 	}
-}
 
-impl Traceable for Triangle {
-	// Ray-Triangle Intersection
-	fn intersect(&self, r: &Ray, result: &mut Hit) -> bool {
+	// Per-vertex surface coordinates, which default to the barycentric coordinates
+	pub fn with_uvs(mut self, uv0: Vector2, uv1: Vector2, uv2: Vector2) -> Triangle {
+		self.uv0 = uv0;
+		self.uv1 = uv1;
+		self.uv2 = uv2;
+		self
+	}
+
+	// Distance along the ray to the triangle, and the barycentric coordinates of the hit point, without
+	// filling in the rest of the hit
+	pub fn distance(&self, r: &Ray) -> Option<(f32, f32, f32)> {
 		let p0p1 = self.p1 - self.p0;
 		let p0p2 = self.p2 - self.p0;
 		let pvec = r.direction.cross(p0p2);
 
-		// if the determinant is negative the triangle is backfacing
-		// if the determinant is close to 0, the ray misses the triangle
-		let det = p0p1.dot(pvec).abs(); // double-sided
-								// if det < 1e-6 {
-								// 	return false;
-								// }
+		// if the determinant is negative the triangle is backfacing, which still counts as a hit
+		// (double-sided). If it is 0, the ray is parallel to the triangle and the barycentric
+		// coordinates below are not finite, so it misses.
+		let det = p0p1.dot(pvec);
 
 		let tvec = r.origin - self.p0;
 		let u = tvec.dot(pvec) / det;
 		if !(0.0..=1.0).contains(&u) {
-			return false;
+			return None;
 		};
 
 		let qvec = tvec.cross(p0p1);
 		let v = r.direction.dot(qvec) / det;
 		if v < 0.0 || u + v > 1.0 {
-			return false;
+			return None;
+		};
+
+		Some((p0p2.dot(qvec) / det, u, v))
+	}
+
+	pub fn area(&self) -> f32 {
+		(self.p1 - self.p0).cross(self.p2 - self.p0).length() * 0.5
+	}
+}
+
+impl Traceable for Triangle {
+	// Ray-Triangle Intersection
+	fn intersect(&self, r: &Ray, result: &mut Hit) -> bool {
+		let (t, u, v) = match self.distance(r) {
+			Some(hit) => hit,
+			None => return false,
 		};
 
 		// intersection
-		result.t = p0p2.dot(qvec) / det;
+		result.t = t;
 		result.p = r.origin + r.direction * result.t;
-		result.material = self.material;
 		result.b = Vector3::new(1.0 - u - v, u, v);
+		result.uv = self.uv0 * result.b.x + self.uv1 * result.b.y + self.uv2 * result.b.z;
+
+		// Compute interpolated normal, facing the incoming ray
+		result.ng = self.normal.normalize();
+		let n = (result.b.x * self.n0 + result.b.y * self.n1 + result.b.z * self.n2).normalize();
+		result.n = if result.ng.dot(r.direction) < 0.0 {
+			n
+		} else {
+			-n
+		};
 
-		// Compute interpolated normal
-		result.n = result.b.x * self.n0 + result.b.y * self.n1 + result.b.z * self.n2;
+		// Tangents from the vertex positions and surface coordinates
+		let duv02 = self.uv0 - self.uv2;
+		let duv12 = self.uv1 - self.uv2;
+		let dp02 = self.p0 - self.p2;
+		let dp12 = self.p1 - self.p2;
+		let determinant = duv02.x * duv12.y - duv02.y * duv12.x;
+
+		if determinant.abs() < 1e-8 {
+			let (tangent, bitangent) = orthonormal_basis(result.ng);
+			result.dpdu = tangent;
+			result.dpdv = bitangent;
+		} else {
+			result.dpdu = (dp02 * duv12.y - dp12 * duv02.y) / determinant;
+			result.dpdv = (dp12 * duv02.x - dp02 * duv12.x) / determinant;
+		}
 
 		true
 	}
@@ -107,6 +161,42 @@ impl Traceable for Triangle {
 	fn get_primitive_type(&self) -> PrimitiveType {
 		PrimitiveType::Triangle
 	}
+
+	fn occludes(&self, r: &Ray, t_min: f32, t_max: f32, _scratch: &mut Hit) -> bool {
+		matches!(self.distance(r), Some((t, _, _)) if t > t_min && t < t_max)
+	}
+
+	fn get_material(&self) -> &Material {
+		&self.material
+	}
+
+	fn sample(&self, origin: Vector3, u1: f32, u2: f32) -> Option<LightSample> {
+		// Uniformly sample the area, and convert to solid angle
+		let b = sample_triangle(u1, u2);
+		let p = self.p0 * b.x + self.p1 * b.y + self.p2 * b.z;
+		let uv = self.uv0 * b.x + self.uv1 * b.y + self.uv2 * b.z;
+		let n = (self.p1 - self.p0).cross(self.p2 - self.p0).normalize();
+		let pdf = area_to_solid_angle(1.0 / self.area(), origin, p, n);
+
+		if pdf == 0.0 {
+			return None;
+		}
+
+		Some(LightSample::new(p, n, uv, pdf))
+	}
+
+	fn pdf(&self, origin: Vector3, hit: &Hit) -> f32 {
+		let n = (self.p1 - self.p0).cross(self.p2 - self.p0).normalize();
+		area_to_solid_angle(1.0 / self.area(), origin, hit.p, n)
+	}
+
+	fn bounds(&self) -> Option<AABB> {
+		Some(self.aabb)
+	}
+
+	fn area(&self) -> f32 {
+		Triangle::area(self)
+	}
 }
 
 impl Bounded for Triangle {
@@ -0,0 +1,687 @@
+use acceleration::FlatBVH;
+use bvh::aabb::AABB;
+use bvh::Vector3;
+use ray::Ray;
+
+#[cfg(target_arch = "x86")]
+use std::arch::x86::*;
+#[cfg(target_arch = "x86_64")]
+use std::arch::x86_64::*;
+
+// Wide nodes are at most as deep as the binary hierarchy they are collapsed from, which leaves each level
+// with at most N - 1 siblings waiting on the stack
+const MAX_DEPTH: usize = 64;
+
+// Instruction set used to test the children of a node against a ray
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum BoxTest {
+	Scalar,
+	// 4 boxes at a time
+	Sse2,
+	// 8 boxes at a time
+	Avx2,
+}
+
+impl BoxTest {
+	// Widest instruction set supported by the running CPU
+	pub fn detect() -> BoxTest {
+		if BoxTest::Avx2.is_supported() {
+			BoxTest::Avx2
+		} else if BoxTest::Sse2.is_supported() {
+			BoxTest::Sse2
+		} else {
+			BoxTest::Scalar
+		}
+	}
+
+	pub fn is_supported(self) -> bool {
+		match self {
+			BoxTest::Scalar => true,
+			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+			BoxTest::Sse2 => is_x86_feature_detected!("sse2"),
+			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+			BoxTest::Avx2 => is_x86_feature_detected!("avx2"),
+			#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+			_ => false,
+		}
+	}
+}
+
+// Bounds of up to N children in structure of arrays layout, so they load straight into SIMD registers
+#[derive(Copy, Clone, Debug)]
+#[repr(C, align(32))]
+pub struct WideNode<const N: usize> {
+	pub min_x: [f32; N],
+	pub min_y: [f32; N],
+	pub min_z: [f32; N],
+	pub max_x: [f32; N],
+	pub max_y: [f32; N],
+	pub max_z: [f32; N],
+	// Index of the child node, or of the first primitive for leaves
+	pub children: [u32; N],
+	// Number of primitives for leaves, or 0 for child nodes
+	pub counts: [u32; N],
+	// Number of children in use
+	pub len: u32,
+}
+
+impl<const N: usize> WideNode<N> {
+	fn empty() -> WideNode<N> {
+		WideNode {
+			min_x: [0.0; N],
+			min_y: [0.0; N],
+			min_z: [0.0; N],
+			max_x: [0.0; N],
+			max_y: [0.0; N],
+			max_z: [0.0; N],
+			children: [0; N],
+			counts: [0; N],
+			len: 0,
+		}
+	}
+}
+
+// Ray data shared by every box test
+struct RayBoxes {
+	origin: Vector3,
+	inv_direction: Vector3,
+}
+
+impl RayBoxes {
+	fn new(ray: &Ray) -> RayBoxes {
+		RayBoxes {
+			origin: ray.origin,
+			inv_direction: Vector3::new(
+				1.0 / ray.direction.x,
+				1.0 / ray.direction.y,
+				1.0 / ray.direction.z,
+			),
+		}
+	}
+}
+
+// Multi-way hierarchy [Dammertz et al. 2008], collapsed from a binary SAH hierarchy by pulling up the
+// largest grandchildren until each node has N children. The children of a node are tested against a ray
+// together, N = 4 or 8 boxes at a time, and visited front to back.
+#[derive(Clone, Debug)]
+pub struct WideBVH<const N: usize> {
+	pub nodes: Vec<WideNode<N>>,
+	// Primitive indices, grouped by leaf
+	pub indices: Vec<u32>,
+	//
+	// Only ever set to an instruction set the CPU supports, as the SIMD box tests rely on it
+	box_test: BoxTest,
+	bounds: AABB,
+}
+
+pub type BVH4 = WideBVH<4>;
+pub type BVH8 = WideBVH<8>;
+
+impl<const N: usize> WideBVH<N> {
+	pub fn build(bounds: &[AABB]) -> WideBVH<N> {
+		WideBVH::collapse(&FlatBVH::build(bounds))
+	}
+
+	pub fn collapse(flat: &FlatBVH) -> WideBVH<N> {
+		assert!(N == 4 || N == 8, "Wide BVHs have 4 or 8 children per node");
+
+		let mut bvh = WideBVH {
+			nodes: vec![],
+			indices: flat.indices.clone(),
+			box_test: BoxTest::detect(),
+			bounds: flat.bounds(),
+		};
+
+		if !flat.is_empty() {
+			bvh.collapse_node(flat, vec![0]);
+		}
+
+		bvh
+	}
+
+	// Instruction set to test boxes with, which defaults to the widest one the CPU supports. Falls back
+	// to scalar code when the CPU does not support it.
+	pub fn with_box_test(mut self, box_test: BoxTest) -> WideBVH<N> {
+		self.box_test = if box_test.is_supported() {
+			box_test
+		} else {
+			BoxTest::Scalar
+		};
+		self
+	}
+
+	pub fn box_test(&self) -> BoxTest {
+		self.box_test
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.nodes.is_empty()
+	}
+
+	pub fn bounds(&self) -> AABB {
+		self.bounds
+	}
+
+	// Build a node from binary nodes, opening the largest interior ones until there are N children
+	fn collapse_node(&mut self, flat: &FlatBVH, mut children: Vec<usize>) -> u32 {
+		let node = self.nodes.len();
+		self.nodes.push(WideNode::empty());
+
+		while children.len() < N {
+			let mut largest: Option<(usize, f32)> = None;
+			for (i, &child) in children.iter().enumerate() {
+				if flat.nodes[child].is_leaf() {
+					continue;
+				}
+
+				let area = surface_area(flat, child);
+				if largest.is_none_or(|(_, largest_area)| area > largest_area) {
+					largest = Some((i, area));
+				}
+			}
+
+			match largest {
+				Some((i, _)) => {
+					let child = children[i];
+					children[i] = child + 1;
+					children.push(flat.nodes[child].offset as usize);
+				}
+				None => break,
+			}
+		}
+
+		for (i, &child) in children.iter().enumerate() {
+			let flat_node = &flat.nodes[child];
+			let (index, count) = if flat_node.is_leaf() {
+				(flat_node.offset, flat_node.count as u32)
+			} else {
+				let grandchildren = vec![child + 1, flat_node.offset as usize];
+				(self.collapse_node(flat, grandchildren), 0)
+			};
+
+			let wide = &mut self.nodes[node];
+			wide.min_x[i] = flat_node.min.x;
+			wide.min_y[i] = flat_node.min.y;
+			wide.min_z[i] = flat_node.min.z;
+			wide.max_x[i] = flat_node.max.x;
+			wide.max_y[i] = flat_node.max.y;
+			wide.max_z[i] = flat_node.max.z;
+			wide.children[i] = index;
+			wide.counts[i] = count;
+		}
+		self.nodes[node].len = children.len() as u32;
+
+		node as u32
+	}
+
+	// Test the children of a node, returning a bit mask of those the ray crosses closer than `t_max` along
+	// with the distance to each of them
+	fn intersect_children(
+		&self,
+		node: &WideNode<N>,
+		ray: &RayBoxes,
+		t_max: f32,
+		t_near: &mut [f32; N],
+	) -> u32 {
+		let mask = match self.box_test {
+			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+			BoxTest::Avx2 if N == 8 => unsafe { intersect_avx(node, 0, ray, t_max, t_near) },
+			#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+			BoxTest::Avx2 | BoxTest::Sse2 => {
+				let mut mask = 0;
+				for lane in (0..N).step_by(4) {
+					mask |= unsafe { intersect_sse(node, lane, ray, t_max, t_near) } << lane;
+				}
+				mask
+			}
+			_ => intersect_scalar(node, ray, t_max, t_near),
+		};
+
+		mask & ((1 << node.len) - 1)
+	}
+
+	// Visit the primitives whose bounds the ray crosses, front to back, until `t_max`. `intersect` is given
+	// each primitive and the distance to the closest hit so far, and returns the distance to a closer hit
+	// if there is one, which prunes the rest of the traversal.
+	pub fn intersect<F>(&self, ray: &Ray, mut t_max: f32, mut intersect: F)
+	where
+		F: FnMut(usize, f32) -> Option<f32>,
+	{
+		if self.nodes.is_empty() {
+			return;
+		}
+
+		let boxes = RayBoxes::new(ray);
+
+		// Children waiting to be visited, with their distance and primitive count
+		let mut stack = [(0.0f32, 0u32, 0u32); MAX_DEPTH * 8];
+		let mut stack_size = 0;
+		let mut current = 0;
+		let mut t_near = [0.0; N];
+
+		loop {
+			let node = &self.nodes[current];
+			let mut mask = self.intersect_children(node, &boxes, t_max, &mut t_near);
+
+			// Push the children from the farthest to the nearest, so the nearest is visited first
+			let first = stack_size;
+			while mask != 0 {
+				let i = mask.trailing_zeros() as usize;
+				mask &= mask - 1;
+
+				let entry = (t_near[i], node.children[i], node.counts[i]);
+				let mut slot = stack_size;
+				while slot > first && stack[slot - 1].0 < entry.0 {
+					stack[slot] = stack[slot - 1];
+					slot -= 1;
+				}
+				stack[slot] = entry;
+				stack_size += 1;
+			}
+
+			// Test leaves until reaching the next node, skipping what lies beyond the closest hit
+			loop {
+				if stack_size == 0 {
+					return;
+				}
+				stack_size -= 1;
+				let (t, index, count) = stack[stack_size];
+
+				if t >= t_max {
+					continue;
+				}
+
+				if count == 0 {
+					current = index as usize;
+					break;
+				}
+
+				let start = index as usize;
+				for &primitive in self.indices[start..start + count as usize].iter() {
+					if let Some(t) = intersect(primitive as usize, t_max) {
+						t_max = t;
+					}
+				}
+			}
+		}
+	}
+
+	// Whether `occludes` holds for any of the primitives whose bounds the ray crosses before `t_max`.
+	// Traversal stops at the first one, in no particular order.
+	pub fn occluded<F>(&self, ray: &Ray, t_max: f32, mut occludes: F) -> bool
+	where
+		F: FnMut(usize) -> bool,
+	{
+		if self.nodes.is_empty() {
+			return false;
+		}
+
+		let boxes = RayBoxes::new(ray);
+
+		// Children waiting to be visited, with their primitive count
+		let mut stack = [(0u32, 0u32); MAX_DEPTH * 8];
+		let mut stack_size = 0;
+		let mut current = 0;
+		let mut t_near = [0.0; N];
+
+		loop {
+			let node = &self.nodes[current];
+			let mut mask = self.intersect_children(node, &boxes, t_max, &mut t_near);
+
+			while mask != 0 {
+				let i = mask.trailing_zeros() as usize;
+				mask &= mask - 1;
+
+				stack[stack_size] = (node.children[i], node.counts[i]);
+				stack_size += 1;
+			}
+
+			loop {
+				if stack_size == 0 {
+					return false;
+				}
+				stack_size -= 1;
+				let (index, count) = stack[stack_size];
+
+				if count == 0 {
+					current = index as usize;
+					break;
+				}
+
+				let start = index as usize;
+				for &primitive in self.indices[start..start + count as usize].iter() {
+					if occludes(primitive as usize) {
+						return true;
+					}
+				}
+			}
+		}
+	}
+}
+
+// Surface area of a binary node
+fn surface_area(flat: &FlatBVH, node: usize) -> f32 {
+	let node = &flat.nodes[node];
+	let size = node.max - node.min;
+	2.0 * (size.x * size.y + size.y * size.z + size.z * size.x)
+}
+
+// Bound on the relative error of 3 floating point operations, which the exit distance is widened by
+// [Ize 2013]
+const ROUNDING: f32 = 1.0 + 2.0 * (3.0 * f32::EPSILON * 0.5) / (1.0 - 3.0 * f32::EPSILON * 0.5);
+
+// A ray parallel to an axis and starting on one of the planes of a slab gives 0 * inf = NaN there. It lies
+// inside the closed slab, so the box tests make that axis unbounded rather than letting NaN reject the box.
+
+fn intersect_scalar<const N: usize>(
+	node: &WideNode<N>,
+	ray: &RayBoxes,
+	t_max: f32,
+	t_near: &mut [f32; N],
+) -> u32 {
+	let mut mask = 0;
+
+	for (i, near_out) in t_near.iter_mut().enumerate().take(node.len as usize) {
+		let t0 = (Vector3::new(node.min_x[i], node.min_y[i], node.min_z[i]) - ray.origin)
+			* ray.inv_direction;
+		let t1 = (Vector3::new(node.max_x[i], node.max_y[i], node.max_z[i]) - ray.origin)
+			* ray.inv_direction;
+
+		let mut near = 0.0f32;
+		let mut far = f32::INFINITY;
+		for axis in 0..3 {
+			if !t0[axis].is_nan() && !t1[axis].is_nan() {
+				near = near.max(t0[axis].min(t1[axis]));
+				far = far.min(t0[axis].max(t1[axis]));
+			}
+		}
+		let far = (far * ROUNDING).min(t_max);
+
+		*near_out = near;
+		if near <= far {
+			mask |= 1 << i;
+		}
+	}
+
+	mask
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "sse2")]
+unsafe fn intersect_sse<const N: usize>(
+	node: &WideNode<N>,
+	lane: usize,
+	ray: &RayBoxes,
+	t_max: f32,
+	t_near: &mut [f32; N],
+) -> u32 {
+	let slab = |min: &[f32; N], max: &[f32; N], origin: f32, inv_direction: f32| {
+		let origin = _mm_set1_ps(origin);
+		let inv_direction = _mm_set1_ps(inv_direction);
+		let t0 = _mm_mul_ps(
+			_mm_sub_ps(_mm_loadu_ps(min.as_ptr().add(lane)), origin),
+			inv_direction,
+		);
+		let t1 = _mm_mul_ps(
+			_mm_sub_ps(_mm_loadu_ps(max.as_ptr().add(lane)), origin),
+			inv_direction,
+		);
+
+		// NaN lanes get a near distance of 0, which the clamp below ignores, and a far distance of NaN
+		// (all bits set), which _mm_min_ps ignores in its first operand
+		let nan = _mm_cmpunord_ps(t0, t1);
+		(
+			_mm_andnot_ps(nan, _mm_min_ps(t0, t1)),
+			_mm_or_ps(nan, _mm_max_ps(t0, t1)),
+		)
+	};
+
+	let (near_x, far_x) = slab(&node.min_x, &node.max_x, ray.origin.x, ray.inv_direction.x);
+	let (near_y, far_y) = slab(&node.min_y, &node.max_y, ray.origin.y, ray.inv_direction.y);
+	let (near_z, far_z) = slab(&node.min_z, &node.max_z, ray.origin.z, ray.inv_direction.z);
+
+	let near = _mm_max_ps(
+		_mm_max_ps(near_x, near_y),
+		_mm_max_ps(near_z, _mm_setzero_ps()),
+	);
+	let far = _mm_min_ps(
+		far_z,
+		_mm_min_ps(far_y, _mm_min_ps(far_x, _mm_set1_ps(f32::INFINITY))),
+	);
+	let far = _mm_min_ps(_mm_mul_ps(far, _mm_set1_ps(ROUNDING)), _mm_set1_ps(t_max));
+
+	_mm_storeu_ps(t_near.as_mut_ptr().add(lane), near);
+	_mm_movemask_ps(_mm_cmple_ps(near, far)) as u32
+}
+
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[target_feature(enable = "avx2")]
+unsafe fn intersect_avx<const N: usize>(
+	node: &WideNode<N>,
+	lane: usize,
+	ray: &RayBoxes,
+	t_max: f32,
+	t_near: &mut [f32; N],
+) -> u32 {
+	let slab = |min: &[f32; N], max: &[f32; N], origin: f32, inv_direction: f32| {
+		let origin = _mm256_set1_ps(origin);
+		let inv_direction = _mm256_set1_ps(inv_direction);
+		let t0 = _mm256_mul_ps(
+			_mm256_sub_ps(_mm256_loadu_ps(min.as_ptr().add(lane)), origin),
+			inv_direction,
+		);
+		let t1 = _mm256_mul_ps(
+			_mm256_sub_ps(_mm256_loadu_ps(max.as_ptr().add(lane)), origin),
+			inv_direction,
+		);
+
+		// Same handling of NaN lanes as the SSE version
+		let nan = _mm256_cmp_ps::<_CMP_UNORD_Q>(t0, t1);
+		(
+			_mm256_andnot_ps(nan, _mm256_min_ps(t0, t1)),
+			_mm256_or_ps(nan, _mm256_max_ps(t0, t1)),
+		)
+	};
+
+	let (near_x, far_x) = slab(&node.min_x, &node.max_x, ray.origin.x, ray.inv_direction.x);
+	let (near_y, far_y) = slab(&node.min_y, &node.max_y, ray.origin.y, ray.inv_direction.y);
+	let (near_z, far_z) = slab(&node.min_z, &node.max_z, ray.origin.z, ray.inv_direction.z);
+
+	let near = _mm256_max_ps(
+		_mm256_max_ps(near_x, near_y),
+		_mm256_max_ps(near_z, _mm256_setzero_ps()),
+	);
+	let far = _mm256_min_ps(
+		far_z,
+		_mm256_min_ps(far_y, _mm256_min_ps(far_x, _mm256_set1_ps(f32::INFINITY))),
+	);
+	let far = _mm256_min_ps(
+		_mm256_mul_ps(far, _mm256_set1_ps(ROUNDING)),
+		_mm256_set1_ps(t_max),
+	);
+
+	_mm256_storeu_ps(t_near.as_mut_ptr().add(lane), near);
+	_mm256_movemask_ps(_mm256_cmp_ps::<_CMP_LE_OQ>(near, far)) as u32
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use bvh::aabb::Bounded;
+	use material::Material;
+	use rand::rngs::StdRng;
+	use rand::{Rng, SeedableRng};
+	use triangle::Triangle;
+
+	const T_MIN: f32 = 1e-6;
+
+	// Small random triangles, and axis-aligned rectangles on integer coordinates whose bounds are flat
+	fn random_triangles(rng: &mut StdRng) -> Vec<Triangle> {
+		let mut triangles = vec![];
+
+		for _ in 0..300 {
+			let p = Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 10.0;
+			let mut vertex = || p + Vector3::new(rng.gen(), rng.gen(), rng.gen()) - 0.5;
+			triangles.push(Triangle::new(
+				vertex(),
+				vertex(),
+				vertex(),
+				Material::white(),
+			));
+		}
+
+		for i in 0..60 {
+			let mut coordinate = || rng.gen_range(0..10) as f32;
+			let mut corner = || Vector3::new(coordinate(), coordinate(), coordinate());
+			let (a, mut b) = (corner(), corner());
+			let axis = i % 3;
+			b[axis] = a[axis];
+
+			let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+			let mut c = a;
+			c[u] = b[u];
+			let mut d = a;
+			d[v] = b[v];
+			triangles.push(Triangle::new(a, c, b, Material::white()));
+			triangles.push(Triangle::new(a, b, d, Material::white()));
+		}
+
+		triangles
+	}
+
+	// Random rays, rays with zero direction components, and rays starting on the planes of the rectangles
+	fn random_rays(rng: &mut StdRng) -> Vec<Ray> {
+		let mut rays = vec![];
+
+		for i in 0..2000 {
+			let origin = if i % 2 == 0 {
+				Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 14.0 - 2.0
+			} else {
+				let mut coordinate = || rng.gen_range(-1..11) as f32;
+				Vector3::new(coordinate(), coordinate(), coordinate())
+			};
+
+			let direction = if i % 3 == 0 {
+				Vector3::new(rng.gen(), rng.gen(), rng.gen()) * 2.0 - 1.0
+			} else {
+				// Each component is either zero or of either sign
+				let mut component =
+					|| [-1.0, 0.0, 1.0][rng.gen_range(0..3)] * rng.gen_range(0.5..2.0);
+				Vector3::new(component(), component(), component())
+			};
+
+			if direction != Vector3::new(0.0, 0.0, 0.0) {
+				rays.push(Ray::new(origin, direction));
+			}
+		}
+
+		rays
+	}
+
+	fn brute_force_closest(triangles: &[Triangle], ray: &Ray) -> Option<f32> {
+		triangles
+			.iter()
+			.filter_map(|triangle| triangle.distance(ray))
+			.map(|(t, _, _)| t)
+			.filter(|&t| t > T_MIN)
+			.min_by(|a, b| a.partial_cmp(b).unwrap())
+	}
+
+	fn brute_force_occluded(triangles: &[Triangle], ray: &Ray, t_max: f32) -> bool {
+		triangles.iter().any(
+			|triangle| matches!(triangle.distance(ray), Some((t, _, _)) if t > T_MIN && t < t_max),
+		)
+	}
+
+	fn check_against_brute_force<const N: usize>(seed: u64) {
+		let mut rng = StdRng::seed_from_u64(seed);
+		let triangles = random_triangles(&mut rng);
+		let rays = random_rays(&mut rng);
+		let bounds: Vec<AABB> = triangles.iter().map(|triangle| triangle.aabb()).collect();
+
+		for &box_test in [BoxTest::Scalar, BoxTest::Sse2, BoxTest::Avx2].iter() {
+			if !box_test.is_supported() {
+				continue;
+			}
+			let bvh = WideBVH::<N>::build(&bounds).with_box_test(box_test);
+			assert_eq!(bvh.box_test(), box_test);
+
+			let mut hits = 0;
+			for ray in rays.iter() {
+				let expected = brute_force_closest(&triangles, ray);
+
+				let mut closest = None;
+				bvh.intersect(ray, f32::INFINITY, |index, t_max| {
+					let (t, _, _) = triangles[index].distance(ray)?;
+					if t > T_MIN && t < t_max {
+						closest = Some(t);
+						Some(t)
+					} else {
+						None
+					}
+				});
+				// Triangles sharing an edge can report the same hit a rounding error apart
+				let matches = match (closest, expected) {
+					(Some(t), Some(expected)) => (t - expected).abs() <= expected * 1e-6,
+					(closest, expected) => closest == expected,
+				};
+				assert!(
+					matches,
+					"{:?} BVH{} closest hit of {:?} towards {:?}: {:?} instead of {:?}",
+					box_test, N, ray.origin, ray.direction, closest, expected
+				);
+
+				// Just before and after the closest hit, and without any limit
+				let t = expected.unwrap_or(1.0);
+				for &t_max in [t * 0.999, t * 1.001, f32::INFINITY].iter() {
+					let occluded = bvh.occluded(
+						ray,
+						t_max,
+						|index| matches!(triangles[index].distance(ray), Some((t, _, _)) if t > T_MIN && t < t_max),
+					);
+					assert_eq!(
+						occluded,
+						brute_force_occluded(&triangles, ray, t_max),
+						"{:?} BVH{} occlusion of {:?} towards {:?} before {}",
+						box_test,
+						N,
+						ray.origin,
+						ray.direction,
+						t_max
+					);
+				}
+
+				hits += expected.is_some() as usize;
+			}
+
+			// Make sure the rays exercise both outcomes
+			assert!(
+				hits > rays.len() / 10 && hits < rays.len() * 9 / 10,
+				"{} hits",
+				hits
+			);
+		}
+	}
+
+	#[test]
+	fn bvh4_matches_brute_force() {
+		check_against_brute_force::<4>(1);
+		check_against_brute_force::<4>(2);
+	}
+
+	#[test]
+	fn bvh8_matches_brute_force() {
+		check_against_brute_force::<8>(3);
+		check_against_brute_force::<8>(4);
+	}
+
+	#[test]
+	fn empty_bvh() {
+		let bvh = BVH8::build(&[]);
+		let ray = Ray::new(Vector3::new(0.0, 0.0, 0.0), Vector3::new(0.0, 0.0, 1.0));
+
+		assert!(bvh.is_empty());
+		bvh.intersect(&ray, f32::INFINITY, |_, _| panic!("no primitives to test"));
+		assert!(!bvh.occluded(&ray, f32::INFINITY, |_| panic!("no primitives to test")));
+	}
+}
@@ -0,0 +1,413 @@
+extern crate bvh;
+extern crate rand;
+extern crate smallpt;
+
+use bvh::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use smallpt::*;
+use std::f32::consts::PI;
+
+// Histogram of sampled directions over the whole sphere, in bins of equal solid angle: uniform in cos(theta)
+// and in phi
+const COS_THETA_BINS: usize = 16;
+const PHI_BINS: usize = 32;
+const NUM_SAMPLES: usize = 100_000;
+
+// Hit on a material with the given BSDF, from outside or from inside the medium for dielectrics
+fn material_hit<T: BSDF + 'static>(bsdf: T, albedo: Vector3, inside: bool) -> Hit {
+	let mut hit = Hit::init();
+	hit.material = Material::new(Vector3::new(0.0, 0.0, 0.0), albedo, bsdf);
+	hit.n = Vector3::new(0.0, 0.0, 1.0);
+	hit.ng = Vector3::new(0.0, 0.0, if inside { -1.0 } else { 1.0 });
+	hit
+}
+
+fn white() -> Vector3 {
+	Vector3::new(1.0, 1.0, 1.0)
+}
+
+fn direction(theta: f32, phi: f32) -> Vector3 {
+	Vector3::new(
+		theta.sin() * phi.cos(),
+		theta.sin() * phi.sin(),
+		theta.cos(),
+	)
+}
+
+// Viewing directions from near the normal to grazing, away from the axes of anisotropic distributions
+fn view_directions() -> Vec<Vector3> {
+	[10.0f32, 45.0, 75.0]
+		.iter()
+		.map(|theta| direction(theta.to_radians(), 0.3))
+		.collect()
+}
+
+fn bin(w: Vector3) -> usize {
+	let z = ((w.z + 1.0) * 0.5 * COS_THETA_BINS as f32) as usize;
+	let phi = w.y.atan2(w.x).rem_euclid(2.0 * PI);
+	let p = (phi / (2.0 * PI) * PHI_BINS as f32) as usize;
+	z.min(COS_THETA_BINS - 1) * PHI_BINS + p.min(PHI_BINS - 1)
+}
+
+// Integrate a density over each bin, in (cos(theta), phi) coordinates where area is solid angle
+fn integrate_bins<F: Fn(Vector3) -> f32>(pdf: F) -> Vec<f32> {
+	let z_step = 2.0 / COS_THETA_BINS as f32;
+	let phi_step = 2.0 * PI / PHI_BINS as f32;
+
+	(0..COS_THETA_BINS * PHI_BINS)
+		.map(|i| {
+			let z = -1.0 + (i / PHI_BINS) as f32 * z_step;
+			let phi = (i % PHI_BINS) as f32 * phi_step;
+			let (z, phi) = ([z, z + z_step], [phi, phi + phi_step]);
+			integrate_cell(&pdf, z, phi, midpoints(&pdf, z, phi), 0)
+		})
+		.collect()
+}
+
+// Midpoint rule over a grid of 4x4 points
+fn midpoints<F: Fn(Vector3) -> f32>(pdf: &F, z: [f32; 2], phi: [f32; 2]) -> f32 {
+	let mut sum = 0.0;
+	for a in 0..4 {
+		for b in 0..4 {
+			let z = z[0] + (z[1] - z[0]) * (a as f32 + 0.5) / 4.0;
+			let phi = phi[0] + (phi[1] - phi[0]) * (b as f32 + 0.5) / 4.0;
+			let r = (1.0 - z * z).max(0.0).sqrt();
+			sum += pdf(Vector3::new(r * phi.cos(), r * phi.sin(), z));
+		}
+	}
+	sum / 16.0 * (z[1] - z[0]) * (phi[1] - phi[0])
+}
+
+// Integral over a cell, given its midpoint estimate, refined where the estimate disagrees with the sum over
+// the quarters of the cell to follow the narrow peaks of smooth microfacet lobes
+fn integrate_cell<F: Fn(Vector3) -> f32>(
+	pdf: &F,
+	z: [f32; 2],
+	phi: [f32; 2],
+	estimate: f32,
+	depth: usize,
+) -> f32 {
+	let z_mid = 0.5 * (z[0] + z[1]);
+	let phi_mid = 0.5 * (phi[0] + phi[1]);
+	let quarters = [
+		([z[0], z_mid], [phi[0], phi_mid]),
+		([z[0], z_mid], [phi_mid, phi[1]]),
+		([z_mid, z[1]], [phi[0], phi_mid]),
+		([z_mid, z[1]], [phi_mid, phi[1]]),
+	];
+
+	let estimates = quarters.map(|(z, phi)| midpoints(pdf, z, phi));
+	let refined: f32 = estimates.iter().sum();
+	if depth >= 8 || (refined - estimate).abs() < 1e-5 {
+		return refined;
+	}
+
+	quarters
+		.iter()
+		.zip(estimates.iter())
+		.map(|(&(z, phi), &estimate)| integrate_cell(pdf, z, phi, estimate, depth + 1))
+		.sum()
+}
+
+// Compare a histogram of sampled directions with the integral of their density over each bin. Bins are
+// allowed a few standard deviations of noise, plus some slack for the numerical integration of peaky
+// densities.
+fn check_histogram(name: &str, observed: &[usize], expected: &[f32]) {
+	let total_observed: usize = observed.iter().sum();
+	let total_expected: f32 = expected.iter().sum::<f32>() * NUM_SAMPLES as f32;
+	assert!(
+		(total_observed as f32 - total_expected).abs() < 0.01 * NUM_SAMPLES as f32,
+		"{}: {} samples instead of {}",
+		name,
+		total_observed,
+		total_expected
+	);
+
+	for (i, (&observed, &expected)) in observed.iter().zip(expected.iter()).enumerate() {
+		let expected = expected * NUM_SAMPLES as f32;
+		let tolerance = 5.0 * expected.sqrt() + 0.05 * expected + 10.0;
+		assert!(
+			(observed as f32 - expected).abs() < tolerance,
+			"{}: bin {} has {} samples instead of {}",
+			name,
+			i,
+			observed,
+			expected
+		);
+	}
+}
+
+fn assert_close(name: &str, a: Vector3, b: Vector3) {
+	let scale = a.abs().max(b.abs()).max_element().max(1e-3);
+	assert!(
+		(a - b).abs().max_element() <= 2e-3 * scale,
+		"{}: {:?} != {:?}",
+		name,
+		a,
+		b
+	);
+}
+
+// Checks every sampling method of a BSDF against its evaluation, from the given direction:
+// - the histogram of sampled directions follows `pdf`
+// - the pdf of each non-specular sample is `pdf`, and its weight is eval * cos / pdf
+// - the average weight, which is the energy reflected or transmitted under uniform lighting, is at most 1
+//   for a white material (white furnace test)
+fn check_bsdf(name: &str, hit: &Hit, wo: Vector3) {
+	let bsdf = hit.material.bsdf.clone();
+	let name = format!("{} towards {:?}", name, wo);
+	let mut rng = StdRng::seed_from_u64(7);
+	let mut histogram = vec![0; COS_THETA_BINS * PHI_BINS];
+	let mut energy = Vector3::new(0.0, 0.0, 0.0);
+
+	for _ in 0..NUM_SAMPLES {
+		let sample = match bsdf.sample(hit, wo, rng.gen(), rng.gen(), rng.gen()) {
+			Some(sample) => sample,
+			None => continue,
+		};
+		assert!(
+			sample.weight.is_finite() && sample.weight.min_element() >= 0.0,
+			"{}: weight {:?}",
+			name,
+			sample.weight
+		);
+		energy += sample.weight;
+
+		if sample.specular {
+			continue;
+		}
+
+		let wi = sample.wi;
+		histogram[bin(wi)] += 1;
+
+		let pdf = bsdf.pdf(hit, wo, wi);
+		assert!(
+			(sample.pdf - pdf).abs() <= 2e-3 * pdf,
+			"{}: sampled pdf {} for {:?}, evaluated {}",
+			name,
+			sample.pdf,
+			wi,
+			pdf
+		);
+		assert_close(
+			&format!("{}: weight for {:?}", name, wi),
+			sample.weight,
+			bsdf.eval(hit, wo, wi) * (wi.z.abs() / pdf),
+		);
+	}
+
+	let expected = integrate_bins(|wi| bsdf.pdf(hit, wo, wi));
+	check_histogram(&name, &histogram, &expected);
+
+	let energy = energy / NUM_SAMPLES as f32;
+	assert!(
+		energy.max_element() <= 1.01,
+		"{}: reflects {:?} of the incoming energy",
+		name,
+		energy
+	);
+}
+
+#[test]
+fn ggx_visible_normals() {
+	for &(alpha_x, alpha_y) in [(0.1, 0.1), (0.5, 0.5), (0.2, 0.8), (1.0, 1.0)].iter() {
+		let ggx = GGX::new(alpha_x, alpha_y);
+
+		// Normals are distributed over the projected microsurface, which has the area of the macrosurface
+		let projected_area: f32 = integrate_bins(|m| ggx.d(m) * m.z).iter().sum();
+		assert!(
+			(projected_area - 1.0).abs() < 0.02,
+			"{:?}: projected area {}",
+			ggx,
+			projected_area
+		);
+
+		for wo in view_directions() {
+			let mut rng = StdRng::seed_from_u64(3);
+			let mut histogram = vec![0; COS_THETA_BINS * PHI_BINS];
+			for _ in 0..NUM_SAMPLES {
+				histogram[bin(ggx.sample_visible(wo, rng.gen(), rng.gen()))] += 1;
+			}
+
+			let expected = integrate_bins(|m| ggx.pdf_visible(wo, m));
+			check_histogram(
+				&format!("{:?} towards {:?}", ggx, wo),
+				&histogram,
+				&expected,
+			);
+		}
+	}
+}
+
+#[test]
+fn conductor() {
+	let bsdfs = [
+		("gold", Conductor::gold(0.3)),
+		("rough aluminium", Conductor::aluminium(0.8)),
+		(
+			"brushed iron",
+			Conductor::new(
+				Vector3::new(2.912, 2.950, 2.584),
+				Vector3::new(3.089, 2.932, 2.767),
+				0.5,
+				0.8,
+			),
+		),
+	];
+
+	for &(name, bsdf) in bsdfs.iter() {
+		let hit = material_hit(bsdf, white(), false);
+		for wo in view_directions() {
+			check_bsdf(name, &hit, wo);
+		}
+	}
+}
+
+#[test]
+fn smooth_conductor_is_a_mirror() {
+	let hit = material_hit(Conductor::silver(0.0), white(), false);
+	let wo = direction(0.5, 0.3);
+	let sample = hit.material.bsdf.sample(&hit, wo, 0.5, 0.5, 0.5).unwrap();
+
+	assert!(sample.specular);
+	assert!((sample.wi - Vector3::new(-wo.x, -wo.y, wo.z)).length() < 1e-5);
+	assert!(sample.weight.max_element() <= 1.0);
+	assert_eq!(
+		hit.material.bsdf.eval(&hit, wo, sample.wi),
+		Vector3::new(0.0, 0.0, 0.0)
+	);
+}
+
+#[test]
+fn rough_glass() {
+	for &roughness in [0.3, 0.7].iter() {
+		for &inside in [false, true].iter() {
+			let hit = material_hit(RoughGlass::new(1.5, roughness), white(), inside);
+			let name = format!(
+				"rough glass {} from {}",
+				roughness,
+				if inside { "inside" } else { "outside" }
+			);
+			for wo in view_directions() {
+				check_bsdf(&name, &hit, wo);
+			}
+		}
+	}
+}
+
+#[test]
+fn coated() {
+	let bsdfs = [
+		("smooth plastic", Coated::plastic(0.0)),
+		("plastic", Coated::plastic(0.3)),
+		("rough varnish", Coated::varnish(0.8)),
+	];
+	let albedos = [white(), Vector3::new(0.8, 0.2, 0.1)];
+
+	for &(name, bsdf) in bsdfs.iter() {
+		for &albedo in albedos.iter() {
+			let hit = material_hit(bsdf, albedo, false);
+			for wo in view_directions() {
+				check_bsdf(&format!("{} {:?}", name, albedo), &hit, wo);
+			}
+		}
+	}
+}
+
+// Every sampling check, with a white and a colored base, and from inside for transmissive materials
+fn check_principled(name: &str, bsdf: Principled) {
+	for &albedo in [white(), Vector3::new(0.8, 0.2, 0.1)].iter() {
+		for &inside in [false, true].iter() {
+			if inside && bsdf.transmission == 0.0 {
+				continue;
+			}
+
+			let hit = material_hit(bsdf, albedo, inside);
+			let name = format!("{} {:?} inside {}", name, albedo, inside);
+			for wo in view_directions() {
+				check_bsdf(&name, &hit, wo);
+			}
+		}
+	}
+}
+
+#[test]
+fn principled_dielectric() {
+	check_principled("dielectric", Principled::new(0.0, 0.5, 0.5, 0.0, 0.0, 0.0));
+}
+
+#[test]
+fn principled_metal() {
+	check_principled("metal", Principled::new(1.0, 0.3, 0.5, 0.0, 0.0, 0.0));
+	check_principled("half metal", Principled::new(0.5, 0.6, 0.5, 0.0, 0.0, 0.0));
+}
+
+#[test]
+fn principled_sheen_and_clearcoat() {
+	check_principled(
+		"sheen and clear coat",
+		Principled::new(0.0, 0.7, 0.5, 1.0, 1.0, 0.0),
+	);
+}
+
+#[test]
+fn principled_glass() {
+	check_principled("glass", Principled::new(0.0, 0.3, 0.5, 0.0, 0.0, 1.0));
+	check_principled(
+		"frosted glass",
+		Principled::new(0.2, 0.6, 0.8, 0.0, 0.5, 0.7),
+	);
+}
+
+#[test]
+fn oren_nayar() {
+	for &sigma in [0.3, 1.0].iter() {
+		for &albedo in [white(), Vector3::new(0.8, 0.2, 0.1)].iter() {
+			let hit = material_hit(OrenNayar::new(sigma), albedo, false);
+			for wo in view_directions() {
+				check_bsdf(&format!("Oren-Nayar {} {:?}", sigma, albedo), &hit, wo);
+			}
+		}
+	}
+}
+
+#[test]
+fn smooth_oren_nayar_is_lambertian() {
+	let albedo = Vector3::new(0.8, 0.2, 0.1);
+	let oren_nayar = material_hit(OrenNayar::new(0.0), albedo, false);
+	let diffuse = material_hit(Diffuse, albedo, false);
+	let mut rng = StdRng::seed_from_u64(11);
+
+	for wo in view_directions() {
+		for _ in 0..1000 {
+			let (uc, u1, u2) = (rng.gen(), rng.gen(), rng.gen());
+			let a = oren_nayar
+				.material
+				.bsdf
+				.sample(&oren_nayar, wo, uc, u1, u2)
+				.unwrap();
+			let b = diffuse
+				.material
+				.bsdf
+				.sample(&diffuse, wo, uc, u1, u2)
+				.unwrap();
+
+			assert_eq!(a.wi, b.wi);
+			assert_close("sample weight", a.weight, b.weight);
+			assert!((a.pdf - b.pdf).abs() <= 1e-6 * b.pdf);
+			assert!(!a.specular && !b.specular);
+
+			let wi = a.wi;
+			assert_close(
+				"eval",
+				oren_nayar.material.bsdf.eval(&oren_nayar, wo, wi),
+				diffuse.material.bsdf.eval(&diffuse, wo, wi),
+			);
+			assert_eq!(
+				oren_nayar.material.bsdf.pdf(&oren_nayar, wo, wi),
+				diffuse.material.bsdf.pdf(&diffuse, wo, wi)
+			);
+		}
+	}
+}
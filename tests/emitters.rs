@@ -0,0 +1,234 @@
+extern crate bvh;
+extern crate rand;
+extern crate smallpt;
+
+use bvh::Vector3;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use smallpt::*;
+use std::f32::consts::PI;
+
+// Histogram of sampled directions over the whole sphere, in bins of equal solid angle: uniform in cos(theta)
+// and in phi
+const COS_THETA_BINS: usize = 16;
+const PHI_BINS: usize = 32;
+const NUM_SAMPLES: usize = 100_000;
+
+fn emissive() -> Material {
+	Material::new(
+		Vector3::new(1.0, 1.0, 1.0),
+		Vector3::new(0.0, 0.0, 0.0),
+		Diffuse,
+	)
+}
+
+fn bin(w: Vector3) -> usize {
+	let z = ((w.z + 1.0) * 0.5 * COS_THETA_BINS as f32) as usize;
+	let phi = w.y.atan2(w.x).rem_euclid(2.0 * PI);
+	let p = (phi / (2.0 * PI) * PHI_BINS as f32) as usize;
+	z.min(COS_THETA_BINS - 1) * PHI_BINS + p.min(PHI_BINS - 1)
+}
+
+// Integrate a density over each bin, in (cos(theta), phi) coordinates where area is solid angle
+fn integrate_bins<F: Fn(Vector3) -> f32>(pdf: F) -> Vec<f32> {
+	let z_step = 2.0 / COS_THETA_BINS as f32;
+	let phi_step = 2.0 * PI / PHI_BINS as f32;
+
+	(0..COS_THETA_BINS * PHI_BINS)
+		.map(|i| {
+			let z = -1.0 + (i / PHI_BINS) as f32 * z_step;
+			let phi = (i % PHI_BINS) as f32 * phi_step;
+			let (z, phi) = ([z, z + z_step], [phi, phi + phi_step]);
+			integrate_cell(&pdf, z, phi, midpoints(&pdf, z, phi), 0)
+		})
+		.collect()
+}
+
+// Midpoint rule over a grid of 4x4 points
+fn midpoints<F: Fn(Vector3) -> f32>(pdf: &F, z: [f32; 2], phi: [f32; 2]) -> f32 {
+	let mut sum = 0.0;
+	for a in 0..4 {
+		for b in 0..4 {
+			let z = z[0] + (z[1] - z[0]) * (a as f32 + 0.5) / 4.0;
+			let phi = phi[0] + (phi[1] - phi[0]) * (b as f32 + 0.5) / 4.0;
+			let r = (1.0 - z * z).max(0.0).sqrt();
+			sum += pdf(Vector3::new(r * phi.cos(), r * phi.sin(), z));
+		}
+	}
+	sum / 16.0 * (z[1] - z[0]) * (phi[1] - phi[0])
+}
+
+// Integral over a cell, given its midpoint estimate, refined where the estimate disagrees with the sum over
+// the quarters of the cell to follow the silhouette of the shape. The first few levels are always refined,
+// so that small shapes aren't missed by every midpoint.
+fn integrate_cell<F: Fn(Vector3) -> f32>(
+	pdf: &F,
+	z: [f32; 2],
+	phi: [f32; 2],
+	estimate: f32,
+	depth: usize,
+) -> f32 {
+	let z_mid = 0.5 * (z[0] + z[1]);
+	let phi_mid = 0.5 * (phi[0] + phi[1]);
+	let quarters = [
+		([z[0], z_mid], [phi[0], phi_mid]),
+		([z[0], z_mid], [phi_mid, phi[1]]),
+		([z_mid, z[1]], [phi[0], phi_mid]),
+		([z_mid, z[1]], [phi_mid, phi[1]]),
+	];
+
+	let estimates = quarters.map(|(z, phi)| midpoints(pdf, z, phi));
+	let refined: f32 = estimates.iter().sum();
+	if depth >= 8 || (depth >= 3 && (refined - estimate).abs() < 1e-5) {
+		return refined;
+	}
+
+	quarters
+		.iter()
+		.zip(estimates.iter())
+		.map(|(&(z, phi), &estimate)| integrate_cell(pdf, z, phi, estimate, depth + 1))
+		.sum()
+}
+
+// Compare a histogram of sampled directions with the integral of their density over each bin, allowing a
+// few standard deviations of noise
+fn check_histogram(name: &str, observed: &[usize], expected: &[f32]) {
+	let total_observed: usize = observed.iter().sum();
+	let total_expected: f32 = expected.iter().sum::<f32>() * NUM_SAMPLES as f32;
+	assert!(
+		(total_observed as f32 - total_expected).abs() < 0.01 * NUM_SAMPLES as f32,
+		"{}: {} samples instead of {}",
+		name,
+		total_observed,
+		total_expected
+	);
+
+	for (i, (&observed, &expected)) in observed.iter().zip(expected.iter()).enumerate() {
+		let expected = expected * NUM_SAMPLES as f32;
+		let tolerance = 5.0 * expected.sqrt() + 0.05 * expected + 10.0;
+		assert!(
+			(observed as f32 - expected).abs() < tolerance,
+			"{}: bin {} has {} samples instead of {}",
+			name,
+			i,
+			observed,
+			expected
+		);
+	}
+}
+
+// Checks light sampling of a shape from `origin` against its density, like BSDFs are:
+// - every sampled point is hit by a ray towards it, and `pdf` at that hit is the density it was sampled with
+// - the histogram of directions towards sampled points follows `pdf` at the hits of rays in those directions
+fn check_shape(name: &str, shape: &dyn Traceable, origin: Vector3) {
+	let name = format!("{} from {:?}", name, origin);
+	let mut rng = StdRng::seed_from_u64(3);
+	let mut histogram = vec![0; COS_THETA_BINS * PHI_BINS];
+
+	for _ in 0..NUM_SAMPLES {
+		let sample = match shape.sample(origin, rng.gen(), rng.gen()) {
+			Some(sample) => sample,
+			None => continue,
+		};
+
+		let to_p = sample.p - origin;
+		let distance = to_p.length();
+		let mut hit = Hit::init();
+		assert!(
+			shape.intersect(&Ray::new(origin, to_p / distance), &mut hit),
+			"{}: sampled {:?}, which a ray doesn't hit",
+			name,
+			sample.p
+		);
+		assert!((hit.t - distance).abs() < 1e-3 * distance);
+
+		let pdf = shape.pdf(origin, &hit);
+		assert!(
+			(sample.pdf - pdf).abs() <= 1e-3 * pdf,
+			"{}: sampled {:?} with pdf {}, evaluated {}",
+			name,
+			sample.p,
+			sample.pdf,
+			pdf
+		);
+
+		histogram[bin(to_p / distance)] += 1;
+	}
+
+	let expected = integrate_bins(|direction| {
+		let mut hit = Hit::init();
+		if shape.intersect(&Ray::new(origin, direction), &mut hit) && hit.t > 0.0 {
+			shape.pdf(origin, &hit)
+		} else {
+			0.0
+		}
+	});
+	check_histogram(&name, &histogram, &expected);
+}
+
+fn rectangle() -> Rectangle {
+	Rectangle::new(
+		Vector3::new(0.0, 0.0, 0.0),
+		Vector3::new(0.0, 0.0, 1.0),
+		Vector3::new(1.0, 0.0, 0.0),
+		Vector3::new(0.0, 1.0, 0.0),
+		2.0,
+		1.0,
+		emissive(),
+	)
+}
+
+#[test]
+fn rectangle_front() {
+	let rectangle = rectangle();
+	for &origin in [Vector3::new(0.3, -0.2, 2.0), Vector3::new(3.0, 1.0, 0.5)].iter() {
+		check_shape("rectangle", &rectangle, origin);
+	}
+}
+
+// Rays don't hit the back of a rectangle, so it can't be sampled from behind either
+#[test]
+fn rectangle_back() {
+	let rectangle = rectangle();
+	let origin = Vector3::new(0.3, -0.2, -2.0);
+	assert!(rectangle.sample(origin, 0.5, 0.5).is_none());
+
+	let mut hit = Hit::init();
+	let front = Vector3::new(0.3, -0.2, 2.0);
+	assert!(rectangle.intersect(&Ray::new(front, Vector3::new(0.0, 0.0, -1.0)), &mut hit));
+	assert_eq!(rectangle.pdf(origin, &hit), 0.0);
+	assert!(!rectangle.intersect(
+		&Ray::new(origin, Vector3::new(0.0, 0.0, 1.0)),
+		&mut Hit::init()
+	));
+}
+
+#[test]
+fn sphere_outside() {
+	let sphere = Sphere::new(1.0, Vector3::new(0.5, 0.2, -0.3), emissive());
+	for &origin in [Vector3::new(3.0, 0.0, 1.0), Vector3::new(0.5, 1.3, -0.3)].iter() {
+		check_shape("sphere", &sphere, origin);
+	}
+}
+
+#[test]
+fn sphere_inside() {
+	let sphere = Sphere::new(1.0, Vector3::new(0.5, 0.2, -0.3), emissive());
+	for &origin in [Vector3::new(0.5, 0.2, -0.3), Vector3::new(0.9, 0.0, 0.2)].iter() {
+		check_shape("sphere", &sphere, origin);
+	}
+}
+
+// Triangles are hit, and emit, on both sides
+#[test]
+fn triangle() {
+	let triangle = Triangle::new(
+		Vector3::new(-1.0, -0.5, 0.0),
+		Vector3::new(1.5, -0.2, 0.3),
+		Vector3::new(0.0, 1.0, -0.2),
+		emissive(),
+	);
+	for &origin in [Vector3::new(0.2, 0.1, 1.5), Vector3::new(-0.5, 0.3, -1.0)].iter() {
+		check_shape("triangle", &triangle, origin);
+	}
+}
@@ -0,0 +1,249 @@
+extern crate bvh;
+extern crate smallpt;
+
+use bvh::Vector3;
+use smallpt::*;
+use std::env;
+use std::fs::File;
+use std::io::Write;
+use std::path::PathBuf;
+
+const IOR: f32 = 1.5;
+const WIDTH: usize = 32;
+const HEIGHT: usize = 32;
+
+// Hit on glass with the shading normal along +z, facing the viewer. The geometric normal faces outwards,
+// so it points away from the viewer when the ray comes from inside the medium.
+fn glass_hit(inside: bool) -> Hit {
+	let mut hit = Hit::init();
+	hit.material = Material::new(
+		Vector3::new(0.0, 0.0, 0.0),
+		Vector3::new(0.9, 0.8, 0.7),
+		Glass::new(IOR),
+	);
+	hit.n = Vector3::new(0.0, 0.0, 1.0);
+	hit.ng = Vector3::new(0.0, 0.0, if inside { -1.0 } else { 1.0 });
+	hit
+}
+
+// Direction at `theta` from the normal, in the xz plane
+fn direction(theta: f32) -> Vector3 {
+	Vector3::new(theta.sin(), 0.0, theta.cos())
+}
+
+// Schlick's approximation, with the cosine on the outside of the medium
+fn reflectance(cos_outside: f32) -> f32 {
+	let r0 = ((IOR - 1.0) / (IOR + 1.0)).powi(2);
+	r0 + (1.0 - r0) * (1.0 - cos_outside).powi(5)
+}
+
+fn assert_close(a: Vector3, b: Vector3) {
+	assert!((a - b).abs().max_element() < 1e-5, "{:?} != {:?}", a, b);
+}
+
+// Sample both the reflection (uc = 0) and the refraction (uc just below 1), and check them against
+// the expected directions and Fresnel reflectance
+fn check_refraction(inside: bool, theta_o: f32, theta_t: f32, cos_outside: f32) {
+	let hit = glass_hit(inside);
+	let wo = direction(theta_o);
+	let r = reflectance(cos_outside);
+	let probability = 0.25 + 0.5 * r;
+
+	let reflected = Glass::new(IOR).sample(&hit, wo, 0.0, 0.5, 0.5).unwrap();
+	assert!(reflected.specular);
+	assert_close(reflected.wi, Vector3::new(-wo.x, -wo.y, wo.z));
+	assert!((reflected.pdf - probability).abs() < 1e-5);
+	assert_close(reflected.weight, hit.material.albedo * (r / probability));
+
+	let refracted = Glass::new(IOR).sample(&hit, wo, 0.999, 0.5, 0.5).unwrap();
+	assert!(refracted.specular);
+	assert_close(refracted.wi, -direction(theta_t));
+	assert!((refracted.pdf - (1.0 - probability)).abs() < 1e-5);
+	assert_close(
+		refracted.weight,
+		hit.material.albedo * ((1.0 - r) / (1.0 - probability)),
+	);
+
+	// Averaged over the choice of lobe, the weights split the albedo between reflection and refraction
+	assert_close(
+		reflected.weight * reflected.pdf + refracted.weight * refracted.pdf,
+		hit.material.albedo,
+	);
+}
+
+#[test]
+fn refraction_entering_the_medium() {
+	let theta_o = 45f32.to_radians();
+	let theta_t = (theta_o.sin() / IOR).asin();
+	check_refraction(false, theta_o, theta_t, theta_o.cos());
+}
+
+#[test]
+fn refraction_leaving_the_medium() {
+	let theta_o = 30f32.to_radians();
+	let theta_t = (theta_o.sin() * IOR).asin();
+	check_refraction(true, theta_o, theta_t, theta_t.cos());
+}
+
+#[test]
+fn normal_incidence() {
+	for &inside in [false, true].iter() {
+		check_refraction(inside, 0.0, 0.0, 1.0);
+	}
+	assert!((reflectance(1.0) - 0.04).abs() < 1e-6);
+}
+
+#[test]
+fn total_internal_reflection_from_inside() {
+	let critical = (1.0 / IOR).asin();
+	let hit = glass_hit(true);
+
+	for &theta_o in [critical + 0.01, 60f32.to_radians(), 89f32.to_radians()].iter() {
+		let wo = direction(theta_o);
+
+		for &uc in [0.0, 0.5, 0.999].iter() {
+			let sample = Glass::new(IOR).sample(&hit, wo, uc, 0.5, 0.5).unwrap();
+			assert!(sample.specular);
+			assert_close(sample.wi, Vector3::new(-wo.x, -wo.y, wo.z));
+			assert_close(sample.weight, hit.material.albedo);
+			assert_eq!(sample.pdf, 1.0);
+		}
+	}
+
+	// The same directions refract from outside
+	let sample = Glass::new(IOR)
+		.sample(
+			&glass_hit(false),
+			direction(60f32.to_radians()),
+			0.999,
+			0.5,
+			0.5,
+		)
+		.unwrap();
+	assert!(sample.wi.z < 0.0);
+}
+
+// Glass sphere in front of an emissive backdrop crossed by black bars, so that
+// rays refracted in and out of the sphere land on clearly different radiance
+fn glass_scene() -> Scene {
+	let mut scene = Scene::init();
+
+	scene.add(Box::new(Sphere::new(
+		4.0,
+		Vector3::new(0.0, 0.0, 0.0),
+		Material::new(
+			Vector3::new(0.0, 0.0, 0.0),
+			Vector3::new(1.0, 1.0, 1.0),
+			Glass::new(1.5),
+		),
+	)));
+
+	// Backdrop
+	scene.add(Box::new(Rectangle::new(
+		Vector3::new(0.0, 0.0, -10.0),
+		Vector3::new(0.0, 0.0, 1.0),
+		Vector3::new(1.0, 0.0, 0.0),
+		Vector3::new(0.0, 1.0, 0.0),
+		40.0,
+		40.0,
+		Material::new(
+			Vector3::new(1.0, 1.0, 1.0),
+			Vector3::new(0.0, 0.0, 0.0),
+			Diffuse,
+		),
+	)));
+
+	// Bars
+	for i in 0..4 {
+		scene.add(Box::new(Rectangle::new(
+			Vector3::new(0.0, -6.0 + 4.0 * i as f32, -9.0),
+			Vector3::new(0.0, 0.0, 1.0),
+			Vector3::new(1.0, 0.0, 0.0),
+			Vector3::new(0.0, 1.0, 0.0),
+			40.0,
+			1.5,
+			Material::black(),
+		)));
+	}
+
+	scene.add(Box::new(Rectangle::new(
+		Vector3::new(2.0, 0.0, -8.5),
+		Vector3::new(0.0, 0.0, 1.0),
+		Vector3::new(1.0, 0.0, 0.0),
+		Vector3::new(0.0, 1.0, 0.0),
+		1.5,
+		40.0,
+		Material::black(),
+	)));
+
+	scene
+}
+
+// Render with a fixed seed, so that the image is the same on every run
+fn render(samples: u32) -> Vec<Vector3> {
+	let scene = glass_scene();
+	let camera = Camera::new(
+		Vector3::new(0.0, 0.0, 16.0),
+		Vector3::new(0.0, 0.0, -1.0),
+		Vector3::new(1.0, 0.0, 0.0),
+		Vector3::new(0.0, 1.0, 0.0),
+	);
+
+	let mut backbuffer = vec![Vector3::new(0.0, 0.0, 0.0); WIDTH * HEIGHT];
+	let mut num_rays = 0;
+	trace_seeded(
+		&scene,
+		&camera,
+		WIDTH,
+		HEIGHT,
+		samples,
+		&mut backbuffer,
+		&mut num_rays,
+		1,
+	);
+
+	backbuffer
+}
+
+fn reference_path() -> PathBuf {
+	PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/data/glass_sphere.pfm")
+}
+
+// Portable float map, little-endian, bottom-to-top rows
+fn write_pfm(path: &PathBuf, pixels: &[Vector3]) {
+	let mut file = File::create(path).unwrap();
+	write!(file, "PF\n{} {}\n-1.0\n", WIDTH, HEIGHT).unwrap();
+
+	for row in pixels.chunks(WIDTH).rev() {
+		for pixel in row {
+			for c in &[pixel.x, pixel.y, pixel.z] {
+				file.write_all(&c.to_le_bytes()).unwrap();
+			}
+		}
+	}
+}
+
+fn rmse(a: &[Vector3], b: &[Vector3]) -> f32 {
+	let sum: f32 = a
+		.iter()
+		.zip(b.iter())
+		.map(|(a, b)| (*a - *b).length_squared())
+		.sum();
+	(sum / (3 * a.len()) as f32).sqrt()
+}
+
+#[test]
+fn glass_sphere_matches_reference() {
+	// Set SMALLPT_UPDATE_REFERENCE to regenerate the reference image, converged with many more samples
+	if env::var("SMALLPT_UPDATE_REFERENCE").is_ok() {
+		write_pfm(&reference_path(), &render(8192));
+	}
+
+	let reference = Bitmap::open(reference_path(), ColorSpace::Linear).unwrap();
+	assert_eq!((reference.width, reference.height), (WIDTH, HEIGHT));
+
+	// The seeded render always has the same noise, well below what a wrong refraction adds (an IOR of 1.3
+	// instead of 1.5 gives 0.14)
+	let error = rmse(&render(256), &reference.pixels);
+	assert!(error < 0.04, "root mean square error {} too high", error);
+}